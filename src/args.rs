@@ -1,7 +1,13 @@
 //! Argument parsing for ls-wrapper
 //! Zero-dependency argument parser for ls flags
 
-#[derive(Debug, Default)]
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use crate::codepage::CodePage;
+use crate::columns::Column;
+
+#[derive(Debug, Default, Clone)]
 pub struct LsArgs {
     // Display flags
     pub long_format: bool,      // -l
@@ -9,10 +15,15 @@ pub struct LsArgs {
     pub almost_all: bool,       // -A (hidden, but no . ..)
     pub human_readable: bool,   // -h
     pub one_per_line: bool,     // -1
+    pub columns: bool,          // -C (list entries in columns)
+    pub across: bool,           // -x (columns filled across rather than down)
+    pub commas: bool,           // -m (names separated by commas)
     pub recursive: bool,        // -R
     pub directory: bool,        // -d (list dirs themselves, not contents)
     pub classify: bool,         // -F (append indicator)
     pub show_size: bool,        // -s (show size in blocks - on Windows, just show size)
+    pub dereference: bool,      // -L (follow all symbolic links)
+    pub dereference_command_line: bool, // -H (follow links given on the command line)
 
     // Sorting flags
     pub sort_by_time: bool,     // -t
@@ -22,22 +33,47 @@ pub struct LsArgs {
 
     // Output control
     pub color: ColorOption,     // --color
+    pub quoting_style: Option<QuotingStyle>, // --quoting-style, -Q, -b, -N
+    pub hide_control_chars: Option<bool>,    // -q / --show-control-chars
+    pub zero: bool,             // --zero (end each line with NUL)
+    pub hyperlink: Option<ColorOption>, // --hyperlink (OSC 8 links to each file)
+    pub output: OutputFormat,   // --json, --ndjson, --format=csv|tsv
+    pub table_columns: Option<Vec<Column>>, // --columns (for csv/tsv)
+    pub long_columns: Option<Vec<Column>>,  // --long-columns (layout of -l)
 
     // Educational/meta flags
     pub explain: bool,          // --explain (show translation, don't run)
     pub teach: bool,            // --teach (run AND show translation)
     pub native: bool,           // --native (output Windows command only)
+    pub quiet_compat: bool,     // --quiet-compat (no warnings about inexact translations)
+    pub strict: bool,           // --strict (flags a shell drops are errors)
     pub use_powershell: bool,   // --powershell
     pub use_cmd: bool,          // --cmd
+    pub use_builtin: bool,      // --builtin (list with the built-in walker)
+    pub codepage: Option<CodePage>, // --codepage (how the shell's output is encoded)
+    pub worker: bool,           // --worker (keep PowerShell running between calls)
 
     // Help
     pub help: bool,             // --help, -?
     pub version: bool,          // --version
     pub rosetta: bool,          // --rosetta (cheatsheet)
     pub tree: bool,             // --tree (tree view)
+    pub tree_depth: Option<usize>, // --tree -L N / --level=N
+    pub charset: Charset,       // --charset (tree lines)
+    pub du: bool,               // --du (tree: recursive directory sizes)
+
+    // Filters
+    pub ignore: Vec<String>,    // -I, --ignore (never list matching names)
+    pub hide: Vec<String>,      // --hide (unless -a or -A)
+    pub gitignore: bool,        // --gitignore (skip what git ignores)
+    pub no_glob: bool,          // --no-glob (operands are literal names)
+    pub shell_glob: bool,       // --shell-glob (let dir/PowerShell expand)
+    pub git: bool,              // --git (status column in long listings)
+    pub include: Vec<String>,   // --include (tree: only files matching)
 
     // Paths to list
-    pub paths: Vec<String>,
+    pub paths: Vec<PathBuf>,
+    pub after_dashes: usize,    // how many paths at the end came after --
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
@@ -48,25 +84,98 @@ pub enum ColorOption {
     Never,
 }
 
+/// Line-drawing characters for --tree
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Charset {
+    #[default]
+    Unicode,
+    Ascii,
+}
+
+/// Machine-readable output modes
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    /// Output made of records rather than formatted text
+    pub fn is_structured(self) -> bool {
+        self != OutputFormat::Text
+    }
+}
+
+/// How file names are quoted for display (--quoting-style)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuotingStyle {
+    Literal,
+    Shell,
+    ShellAlways,
+    ShellEscape,
+    C,
+    Escape,
+    Locale,
+}
+
+impl QuotingStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "literal" => Some(QuotingStyle::Literal),
+            "shell" => Some(QuotingStyle::Shell),
+            "shell-always" => Some(QuotingStyle::ShellAlways),
+            "shell-escape" => Some(QuotingStyle::ShellEscape),
+            "c" => Some(QuotingStyle::C),
+            "escape" => Some(QuotingStyle::Escape),
+            "locale" | "clocale" => Some(QuotingStyle::Locale),
+            _ => None,
+        }
+    }
+}
+
 impl LsArgs {
     pub fn parse<I, S>(args: I) -> Result<Self, String>
     where
         I: IntoIterator<Item = S>,
-        S: AsRef<str>,
+        S: AsRef<OsStr>,
     {
         let mut result = LsArgs::default();
-        let mut args_iter = args.into_iter().peekable();
+        let args: Vec<S> = args.into_iter().collect();
+
+        // -L takes a level in tree mode, wherever --tree is given
+        result.tree = args
+            .iter()
+            .skip(1)
+            .map(|arg| arg.as_ref())
+            .take_while(|&arg| arg != "--")
+            .any(|arg| arg == "--tree");
+
+        let mut args_iter = args.into_iter();
 
         // Skip program name if present
         args_iter.next();
 
         while let Some(arg) = args_iter.next() {
-            let arg = arg.as_ref();
+            let os_arg = arg.as_ref();
+
+            // Paths needn't be valid Unicode, but options must be
+            let Some(arg) = os_arg.to_str() else {
+                if os_arg.as_encoded_bytes().starts_with(b"-") {
+                    return Err(format!("invalid option '{}'", os_arg.to_string_lossy()));
+                }
+                result.paths.push(PathBuf::from(os_arg));
+                continue;
+            };
 
             if arg == "--" {
                 // Everything after -- is a path
                 for remaining in args_iter {
-                    result.paths.push(remaining.as_ref().to_string());
+                    result.paths.push(PathBuf::from(remaining.as_ref()));
+                    result.after_dashes += 1;
                 }
                 break;
             } else if arg.starts_with("--") {
@@ -74,16 +183,53 @@ impl LsArgs {
                 Self::parse_long_option(&mut result, arg)?;
             } else if arg.starts_with('-') && arg.len() > 1 {
                 // Short option(s) - can be combined like -la
-                Self::parse_short_options(&mut result, arg)?;
+                if let Some(c) = Self::parse_short_options(&mut result, arg)? {
+                    // The option takes its value from the next argument
+                    let value = args_iter
+                        .next()
+                        .ok_or_else(|| format!("Option -{} requires an argument", c))?;
+                    let value = value.as_ref().to_str().ok_or_else(|| {
+                        format!("invalid argument '{}' for -{}", value.as_ref().to_string_lossy(), c)
+                    })?;
+                    Self::apply_short_value(&mut result, c, value)?;
+                }
             } else {
                 // It's a path
-                result.paths.push(arg.to_string());
+                result.paths.push(PathBuf::from(os_arg));
+            }
+        }
+
+        // --zero output is meant for xargs -0, so it can't be laid out in
+        // columns or carry color escapes
+        if result.zero {
+            for (set, flag) in [(result.columns, "-C"), (result.across, "-x"), (result.commas, "-m")] {
+                if set {
+                    return Err(format!("--zero is incompatible with {}", flag));
+                }
+            }
+            if result.color == ColorOption::Always {
+                return Err("--zero is incompatible with --color=always".to_string());
             }
+            result.one_per_line = true;
+        }
+
+        // A git column anywhere turns on the status lookup
+        let columns = result.table_columns.iter().chain(&result.long_columns);
+        if columns.flatten().any(|&c| c == Column::Git) {
+            result.git = true;
+        }
+
+        // --du and the tabular formats only make sense for one of the views
+        if result.du && !result.tree {
+            return Err("--du requires --tree".to_string());
+        }
+        if result.tree && matches!(result.output, OutputFormat::Csv | OutputFormat::Tsv) {
+            return Err("--tree can't be written as csv or tsv; use --json".to_string());
         }
 
         // Default to current directory if no paths specified
         if result.paths.is_empty() {
-            result.paths.push(".".to_string());
+            result.paths.push(PathBuf::from("."));
         }
 
         Ok(result)
@@ -107,6 +253,8 @@ impl LsArgs {
             "directory" => args.directory = true,
             "classify" => args.classify = true,
             "reverse" => args.reverse = true,
+            "dereference" => args.dereference = true,
+            "dereference-command-line" => args.dereference_command_line = true,
 
             "color" => {
                 args.color = match _value {
@@ -117,17 +265,98 @@ impl LsArgs {
                 };
             }
 
+            "quoting-style" => {
+                let value = _value.ok_or("--quoting-style requires an argument")?;
+                args.quoting_style = Some(
+                    QuotingStyle::parse(value)
+                        .ok_or_else(|| format!("Unknown quoting style: {}", value))?,
+                );
+            }
+            "literal" => args.quoting_style = Some(QuotingStyle::Literal),
+            "quote-name" => args.quoting_style = Some(QuotingStyle::C),
+            "escape" => args.quoting_style = Some(QuotingStyle::Escape),
+            "hyperlink" => {
+                args.hyperlink = Some(match _value {
+                    Some("always") | Some("yes") | Some("force") | None => ColorOption::Always,
+                    Some("never") | Some("no") | Some("none") => ColorOption::Never,
+                    Some("auto") | Some("tty") | Some("if-tty") => ColorOption::Auto,
+                    Some(v) => return Err(format!("Unknown hyperlink option: {}", v)),
+                });
+            }
+            "zero" => args.zero = true,
+            "format" => match _value {
+                Some("long") | Some("verbose") => args.long_format = true,
+                Some("single-column") => args.set_layout('1'),
+                Some("vertical") => args.set_layout('C'),
+                Some("across") | Some("horizontal") => args.set_layout('x'),
+                Some("commas") => args.set_layout('m'),
+                Some("csv") => args.output = OutputFormat::Csv,
+                Some("tsv") => args.output = OutputFormat::Tsv,
+                Some("json") => args.output = OutputFormat::Json,
+                Some("ndjson") => args.output = OutputFormat::Ndjson,
+                Some(v) => return Err(format!("Unknown format: {}", v)),
+                None => return Err("--format requires an argument".to_string()),
+            },
+            "columns" => {
+                let value = _value.ok_or("--columns requires an argument")?;
+                args.table_columns = Some(Column::parse_list(value)?);
+            }
+            "long-columns" => {
+                let value = _value.ok_or("--long-columns requires an argument")?;
+                args.long_columns = Some(Column::parse_long_spec(value)?);
+                args.long_format = true;
+            }
+            "json" => args.output = OutputFormat::Json,
+            "ndjson" => args.output = OutputFormat::Ndjson,
+            "hide-control-chars" => args.hide_control_chars = Some(true),
+            "show-control-chars" => args.hide_control_chars = Some(false),
+
             // Educational flags
             "explain" => args.explain = true,
             "teach" => args.teach = true,
             "native" => args.native = true,
+            "quiet-compat" => args.quiet_compat = true,
+            "strict" => args.strict = true,
             "powershell" | "ps" => args.use_powershell = true,
             "cmd" => args.use_cmd = true,
+            "builtin" => args.use_builtin = true,
+            "worker" => args.worker = true,
 
             "help" => args.help = true,
             "version" => args.version = true,
             "rosetta" | "cheatsheet" => args.rosetta = true,
             "tree" => args.tree = true,
+            "du" => args.du = true,
+            "gitignore" => args.gitignore = true,
+            "no-glob" => args.no_glob = true,
+            "shell-glob" => args.shell_glob = true,
+            "git" => args.git = true,
+            "level" => {
+                let value = _value.ok_or("--level requires an argument")?;
+                args.tree_depth = Some(parse_level(value)?);
+            }
+            "codepage" => {
+                let value = _value.ok_or("--codepage requires an argument")?;
+                let page = CodePage::parse(value).ok_or_else(|| format!("Unknown code page: {}", value))?;
+                args.codepage = Some(page);
+            }
+            "charset" => {
+                args.charset = match _value {
+                    Some("unicode") | Some("utf-8") | Some("utf8") => Charset::Unicode,
+                    Some("ascii") => Charset::Ascii,
+                    Some(v) => return Err(format!("Unknown charset: {}", v)),
+                    None => return Err("--charset requires an argument".to_string()),
+                };
+            }
+            "ignore" => args
+                .ignore
+                .push(_value.ok_or("--ignore requires an argument")?.to_string()),
+            "hide" => args
+                .hide
+                .push(_value.ok_or("--hide requires an argument")?.to_string()),
+            "include" => args
+                .include
+                .push(_value.ok_or("--include requires an argument")?.to_string()),
 
             _ => return Err(format!("Unknown option: --{}", name)),
         }
@@ -135,19 +364,38 @@ impl LsArgs {
         Ok(())
     }
 
-    fn parse_short_options(args: &mut LsArgs, opt: &str) -> Result<(), String> {
+    /// Parse a cluster of short options. Returns the option letter if the
+    /// last one takes a value that's in the next argument.
+    fn parse_short_options(args: &mut LsArgs, opt: &str) -> Result<Option<char>, String> {
         // Skip the leading -
-        for c in opt[1..].chars() {
+        for (i, c) in opt.char_indices().skip(1) {
+            // -I PATTERN, and -L LEVEL in tree mode, take a value: the rest
+            // of this argument, or the next one
+            if c == 'I' || (c == 'L' && args.tree) {
+                let rest = &opt[i + 1..];
+                if rest.is_empty() {
+                    return Ok(Some(c));
+                }
+                Self::apply_short_value(args, c, rest)?;
+                return Ok(None);
+            }
+
             match c {
                 'l' => args.long_format = true,
                 'a' => args.all = true,
                 'A' => args.almost_all = true,
                 'h' => args.human_readable = true,
-                '1' => args.one_per_line = true,
+                '1' | 'C' | 'x' | 'm' => args.set_layout(c),
                 'R' => args.recursive = true,
                 'd' => args.directory = true,
                 'F' => args.classify = true,
                 's' => args.show_size = true,
+                'L' => args.dereference = true,
+                'H' => args.dereference_command_line = true,
+                'Q' => args.quoting_style = Some(QuotingStyle::C),
+                'b' => args.quoting_style = Some(QuotingStyle::Escape),
+                'N' => args.quoting_style = Some(QuotingStyle::Literal),
+                'q' => args.hide_control_chars = Some(true),
                 't' => args.sort_by_time = true,
                 'S' => args.sort_by_size = true,
                 'r' => args.reverse = true,
@@ -157,10 +405,35 @@ impl LsArgs {
             }
         }
 
+        Ok(None)
+    }
+
+    /// -1, -C, -x and -m each pick how names are laid out, the last one
+    /// winning
+    fn set_layout(&mut self, flag: char) {
+        self.one_per_line = flag == '1';
+        self.columns = flag == 'C';
+        self.across = flag == 'x';
+        self.commas = flag == 'm';
+    }
+
+    fn apply_short_value(args: &mut LsArgs, opt: char, value: &str) -> Result<(), String> {
+        match opt {
+            'I' => args.ignore.push(value.to_string()),
+            'L' => args.tree_depth = Some(parse_level(value)?),
+            _ => unreachable!("-{} takes no value", opt),
+        }
         Ok(())
     }
 }
 
+fn parse_level(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(level) if level > 0 => Ok(level),
+        _ => Err(format!("Invalid level: {} (expected a number greater than 0)", value)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,6 +449,148 @@ mod tests {
     fn test_path() {
         let args = LsArgs::parse(["ls", "-l", "./src"]).unwrap();
         assert!(args.long_format);
-        assert_eq!(args.paths, vec!["./src"]);
+        assert_eq!(args.paths, [PathBuf::from("./src")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_unicode_arguments() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let name = OsString::from_vec(b"caf\xe9".to_vec());
+        let args = LsArgs::parse([OsString::from("ls"), OsString::from("-l"), name.clone()]).unwrap();
+        assert!(args.long_format);
+        assert_eq!(args.paths, [PathBuf::from(&name)]);
+
+        let after_dashes = [OsString::from("ls"), OsString::from("--"), OsString::from_vec(b"-\xff".to_vec())];
+        assert_eq!(LsArgs::parse(after_dashes).unwrap().paths[0].as_os_str().len(), 2);
+
+        // Options and their values still have to be text
+        assert!(LsArgs::parse([OsString::from("ls"), OsString::from_vec(b"--\xff".to_vec())]).is_err());
+        assert!(LsArgs::parse([OsString::from("ls"), OsString::from("-I"), name]).is_err());
+    }
+
+    #[test]
+    fn test_dereference_flags() {
+        let args = LsArgs::parse(["ls", "-lL"]).unwrap();
+        assert!(args.dereference);
+        assert!(!args.dereference_command_line);
+
+        let args = LsArgs::parse(["ls", "-H", "--dereference"]).unwrap();
+        assert!(args.dereference);
+        assert!(args.dereference_command_line);
+    }
+
+    #[test]
+    fn test_quoting_flags() {
+        let args = LsArgs::parse(["ls", "--quoting-style=shell-always"]).unwrap();
+        assert_eq!(args.quoting_style, Some(QuotingStyle::ShellAlways));
+
+        // The last quoting option wins
+        let args = LsArgs::parse(["ls", "-Q", "-N", "-q"]).unwrap();
+        assert_eq!(args.quoting_style, Some(QuotingStyle::Literal));
+        assert_eq!(args.hide_control_chars, Some(true));
+
+        assert!(LsArgs::parse(["ls", "--quoting-style=fancy"]).is_err());
+    }
+
+    #[test]
+    fn test_format_and_columns() {
+        let args = LsArgs::parse(["ls", "--format=tsv", "--columns=name,owner"]).unwrap();
+        assert_eq!(args.output, OutputFormat::Tsv);
+        assert_eq!(args.table_columns, Some(vec![Column::Name, Column::Owner]));
+
+        let args = LsArgs::parse(["ls", "--format=long"]).unwrap();
+        assert!(args.long_format);
+
+        assert!(LsArgs::parse(["ls", "--format=xml"]).is_err());
+        assert!(LsArgs::parse(["ls", "--columns=name,bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_long_columns_imply_long_format() {
+        let args = LsArgs::parse(["ls", "--long-columns=windows"]).unwrap();
+        assert!(args.long_format);
+        assert_eq!(args.long_columns.map(|c| c.len()), Some(4));
+    }
+
+    #[test]
+    fn test_tree_level_and_filters() {
+        let args = LsArgs::parse(["ls", "--tree", "-L", "2", "-a", "src"]).unwrap();
+        assert_eq!(args.tree_depth, Some(2));
+        assert!(!args.dereference);
+        assert_eq!(args.paths, [PathBuf::from("src")]);
+
+        let args = LsArgs::parse(["ls", "--tree", "-aL3", "-I", "target", "-I*.o"]).unwrap();
+        assert_eq!(args.tree_depth, Some(3));
+        assert_eq!(args.ignore, vec!["target", "*.o"]);
+
+        // --tree can come after the level
+        let args = LsArgs::parse(["ls", "-L", "1", "--tree", "d"]).unwrap();
+        assert_eq!(args.tree_depth, Some(1));
+        assert!(!args.dereference);
+        assert_eq!(args.paths, [PathBuf::from("d")]);
+
+        // Outside tree mode -L still means --dereference
+        let args = LsArgs::parse(["ls", "-L"]).unwrap();
+        assert!(args.dereference);
+        let args = LsArgs::parse(["ls", "-L", "a", "--", "--tree"]).unwrap();
+        assert!(args.dereference);
+        assert_eq!(args.after_dashes, 1);
+
+        assert!(LsArgs::parse(["ls", "--tree", "-L", "x"]).is_err());
+        assert!(LsArgs::parse(["ls", "-I"]).is_err());
+    }
+
+    #[test]
+    fn test_tree_output_modes() {
+        let args = LsArgs::parse(["ls", "--tree", "--du", "--json"]).unwrap();
+        assert!(args.du);
+        assert_eq!(args.output, OutputFormat::Json);
+
+        assert!(LsArgs::parse(["ls", "--du"]).is_err());
+        assert!(LsArgs::parse(["ls", "--tree", "--format=csv"]).is_err());
+    }
+
+    #[test]
+    fn test_codepage() {
+        let args = LsArgs::parse(["ls", "--codepage=850"]).unwrap();
+        assert_eq!(args.codepage.map(CodePage::name).as_deref(), Some("cp850"));
+        assert!(LsArgs::parse(["ls", "--codepage=932"]).is_err());
+        assert!(LsArgs::parse(["ls", "--codepage"]).is_err());
+    }
+
+    #[test]
+    fn test_git_column() {
+        assert!(LsArgs::parse(["ls", "-l", "--git"]).unwrap().git);
+        assert!(LsArgs::parse(["ls", "--long-columns=git,name"]).unwrap().git);
+        assert!(!LsArgs::parse(["ls", "-l"]).unwrap().git);
+    }
+
+    #[test]
+    fn test_last_layout_wins() {
+        let args = LsArgs::parse(["ls", "-1", "-C"]).unwrap();
+        assert!(args.columns && !args.one_per_line);
+        let args = LsArgs::parse(["ls", "-C1"]).unwrap();
+        assert!(args.one_per_line && !args.columns);
+        let args = LsArgs::parse(["ls", "-1", "--format=vertical"]).unwrap();
+        assert!(args.columns && !args.one_per_line);
+        let args = LsArgs::parse(["ls", "-C", "--format=horizontal"]).unwrap();
+        assert!(args.across && !args.columns);
+        let args = LsArgs::parse(["ls", "-x", "--format=commas"]).unwrap();
+        assert!(args.commas && !args.across);
+    }
+
+    #[test]
+    fn test_zero() {
+        let args = LsArgs::parse(["ls", "--zero"]).unwrap();
+        assert!(args.zero);
+        assert!(args.one_per_line);
+
+        assert!(LsArgs::parse(["ls", "--zero", "-C"]).is_err());
+        assert!(LsArgs::parse(["ls", "-C", "--zero", "-1"]).is_ok());
+        assert!(LsArgs::parse(["ls", "--color=always", "--zero"]).is_err());
+        assert!(LsArgs::parse(["ls", "--zero", "--color=never"]).is_ok());
     }
 }
@@ -0,0 +1,266 @@
+//! Named columns for tabular output (--columns, --long-columns)
+//!
+//! Each column maps onto both an Entry field for the built-in renderer and
+//! a PowerShell property, so every backend emits the same table.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::git;
+use crate::json;
+use crate::native::{self, Entry};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Name,
+    Size,
+    Mtime,
+    Ctime,
+    Owner,
+    Attrs,
+    Ext,
+    Path,
+    Perms,
+    Atime,
+    Target,
+    ShortName,
+    Blocks,
+    Git,
+}
+
+/// Named --long-columns layouts
+const PRESETS: [(&str, &[Column]); 4] = [
+    ("gnu", &Column::LONG_DEFAULT),
+    ("windows", &[Column::Attrs, Column::Mtime, Column::Size, Column::Name]),
+    ("times", &[Column::Ctime, Column::Mtime, Column::Atime, Column::Name]),
+    (
+        "full",
+        &[
+            Column::Perms,
+            Column::Attrs,
+            Column::Owner,
+            Column::Size,
+            Column::Ctime,
+            Column::Mtime,
+            Column::Atime,
+            Column::ShortName,
+            Column::Name,
+            Column::Target,
+        ],
+    ),
+];
+
+impl Column {
+    pub const ALL: [Column; 14] = [
+        Column::Name,
+        Column::Size,
+        Column::Mtime,
+        Column::Ctime,
+        Column::Owner,
+        Column::Attrs,
+        Column::Ext,
+        Column::Path,
+        Column::Perms,
+        Column::Atime,
+        Column::Target,
+        Column::ShortName,
+        Column::Blocks,
+        Column::Git,
+    ];
+
+    /// Columns used when --columns isn't given
+    pub const DEFAULT: [Column; 4] = [Column::Name, Column::Size, Column::Mtime, Column::Path];
+
+    /// The ls -l layout, used when --long-columns isn't given
+    pub const LONG_DEFAULT: [Column; 5] = [
+        Column::Perms,
+        Column::Size,
+        Column::Mtime,
+        Column::Name,
+        Column::Target,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Size => "size",
+            Column::Mtime => "mtime",
+            Column::Ctime => "ctime",
+            Column::Owner => "owner",
+            Column::Attrs => "attrs",
+            Column::Ext => "ext",
+            Column::Path => "path",
+            Column::Perms => "perms",
+            Column::Atime => "atime",
+            Column::Target => "target",
+            Column::ShortName => "shortname",
+            Column::Blocks => "blocks",
+            Column::Git => "git",
+        }
+    }
+
+    /// Whether the column is numeric and right-aligned in tables
+    pub fn is_numeric(self) -> bool {
+        matches!(self, Column::Size | Column::Blocks)
+    }
+
+    /// Parse a --long-columns spec: a preset name or a column list
+    pub fn parse_long_spec(spec: &str) -> Result<Vec<Column>, String> {
+        if let Some((_, columns)) = PRESETS.iter().find(|(name, _)| *name == spec) {
+            return Ok(columns.to_vec());
+        }
+        Column::parse_list(spec).map_err(|e| {
+            let presets: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+            format!("{}; presets: {}", e, presets.join(", "))
+        })
+    }
+
+    /// Parse a comma-separated list such as "name,size,mtime"
+    pub fn parse_list(spec: &str) -> Result<Vec<Column>, String> {
+        let mut columns = Vec::new();
+        for word in spec.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+            match Column::ALL.iter().find(|c| c.name() == word) {
+                Some(column) => columns.push(*column),
+                None => {
+                    let valid: Vec<&str> = Column::ALL.iter().map(|c| c.name()).collect();
+                    return Err(format!(
+                        "Unknown column: {} (valid columns: {})",
+                        word,
+                        valid.join(", ")
+                    ));
+                }
+            }
+        }
+        if columns.is_empty() {
+            return Err("--columns needs at least one column".to_string());
+        }
+        Ok(columns)
+    }
+
+    /// The column's value for an entry, as plain text
+    pub fn value(self, entry: &Entry) -> String {
+        match self {
+            Column::Name => entry.name.clone(),
+            Column::Size => entry.size.to_string(),
+            Column::Mtime => entry.modified.map(json::iso_time).unwrap_or_default(),
+            Column::Ctime => entry.changed.map(json::iso_time).unwrap_or_default(),
+            Column::Owner => entry.uid.map(user_name).unwrap_or_default(),
+            Column::Attrs => json::attribute_names(entry).join(" "),
+            Column::Ext => Path::new(&entry.name)
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default(),
+            Column::Path => std::path::absolute(&entry.path)
+                .unwrap_or_else(|_| entry.path.clone())
+                .to_string_lossy()
+                .into_owned(),
+            Column::Perms => native::permissions_string(entry),
+            Column::Atime => entry.accessed.map(json::iso_time).unwrap_or_default(),
+            Column::Target => entry
+                .link_target
+                .as_ref()
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Column::ShortName => native::short_name(&entry.path).unwrap_or_default(),
+            Column::Blocks => entry.blocks.to_string(),
+            Column::Git => entry.git_status.map(git::format).unwrap_or_default(),
+        }
+    }
+
+    /// Select-Object property producing the same value in PowerShell
+    pub fn ps_property(self) -> &'static str {
+        match self {
+            Column::Name => "@{N='name';E={$_.Name}}",
+            Column::Size => "@{N='size';E={if ($_.PSIsContainer) {0} else {$_.Length}}}",
+            Column::Mtime => "@{N='mtime';E={$_.LastWriteTimeUtc.ToString(\"yyyy-MM-dd'T'HH:mm:ss'Z'\")}}",
+            Column::Ctime => "@{N='ctime';E={$_.CreationTimeUtc.ToString(\"yyyy-MM-dd'T'HH:mm:ss'Z'\")}}",
+            Column::Owner => "@{N='owner';E={(Get-Acl -LiteralPath $_.FullName).Owner}}",
+            Column::Attrs => "@{N='attrs';E={$_.Attributes.ToString().ToLower() -replace ', ', ' '}}",
+            Column::Ext => "@{N='ext';E={$_.Extension}}",
+            Column::Path => "@{N='path';E={$_.FullName}}",
+            Column::Perms => "@{N='perms';E={$_.Mode}}",
+            Column::Atime => "@{N='atime';E={$_.LastAccessTimeUtc.ToString(\"yyyy-MM-dd'T'HH:mm:ss'Z'\")}}",
+            Column::Target => "@{N='target';E={if ($_.LinkTarget) {$_.LinkTarget} else {\"$($_.Target)\"}}}",
+            Column::ShortName => "@{N='shortname';E={(Get-ShortName $_)}}",
+            Column::Blocks => "@{N='blocks';E={[math]::Ceiling($_.Length / 1024)}}",
+            Column::Git => "@{N='git';E={(git status --porcelain=v1 --ignored -- $_.FullName | Select-Object -First 1) -replace '^(..).*', '$1'}}",
+        }
+    }
+
+    /// Format-Table property for the long listing. Name is left to the
+    /// caller, which decorates it with link targets and hyperlinks.
+    pub fn ps_long_property(self) -> &'static str {
+        match self {
+            Column::Name => "Name",
+            Column::Size => "Length",
+            Column::Mtime => "LastWriteTime",
+            Column::Ctime => "CreationTime",
+            Column::Atime => "LastAccessTime",
+            Column::Perms => "Mode",
+            Column::Attrs => "Attributes",
+            Column::Ext => "Extension",
+            Column::Path => "FullName",
+            Column::Owner => "@{N='Owner';E={(Get-Acl -LiteralPath $_.FullName).Owner}}",
+            Column::Target => "@{N='Target';E={if ($_.LinkTarget) {$_.LinkTarget} else {\"$($_.Target)\"}}}",
+            Column::ShortName => "@{N='ShortName';E={(Get-ShortName $_)}}",
+            Column::Blocks => "@{N='Blocks';E={[math]::Ceiling($_.Length / 1024)}}",
+            Column::Git => "@{N='Git';E={(git status --porcelain=v1 --ignored -- $_.FullName | Select-Object -First 1) -replace '^(..).*', '$1'}}",
+        }
+    }
+}
+
+/// PowerShell has no property for 8.3 names; defined ahead of the pipeline
+/// when a shortname column is used
+pub const PS_SHORT_NAME_FUNCTION: &str = "function Get-ShortName($f) { \
+    $fso = New-Object -ComObject Scripting.FileSystemObject; \
+    if ($f.PSIsContainer) {$fso.GetFolder($f.FullName).ShortName} else {$fso.GetFile($f.FullName).ShortName} }; ";
+
+/// Look up a user name from /etc/passwd, falling back to the numeric id
+fn user_name(uid: u32) -> String {
+    static USERS: OnceLock<HashMap<u32, String>> = OnceLock::new();
+    let users = USERS.get_or_init(|| {
+        std::fs::read_to_string("/etc/passwd")
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(':');
+                let name = fields.next()?;
+                let id = fields.nth(1)?.parse().ok()?;
+                Some((id, name.to_string()))
+            })
+            .collect()
+    });
+    users.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            Column::parse_list("name, size,ext").unwrap(),
+            vec![Column::Name, Column::Size, Column::Ext]
+        );
+
+        let err = Column::parse_list("name,colour").unwrap_err();
+        assert!(err.contains("colour"));
+        assert!(err.contains("name, size, mtime, ctime, owner, attrs, ext, path, perms"));
+        assert!(Column::parse_list("").is_err());
+    }
+
+    #[test]
+    fn test_parse_long_spec() {
+        assert_eq!(Column::parse_long_spec("gnu").unwrap(), Column::LONG_DEFAULT.to_vec());
+        assert_eq!(
+            Column::parse_long_spec("perms,size,ctime,name,target").unwrap(),
+            vec![Column::Perms, Column::Size, Column::Ctime, Column::Name, Column::Target]
+        );
+
+        let err = Column::parse_long_spec("perms,mode").unwrap_err();
+        assert!(err.contains("Unknown column: mode"));
+        assert!(err.contains("presets: gnu, windows, times, full"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Tabular export: --format=csv and --format=tsv
+//!
+//! CSV follows RFC 4180: a header row, CRLF line ends, and fields quoted
+//! only when they contain a comma, quote or line break. TSV can't quote, so
+//! tabs, line breaks and backslashes in values are backslash-escaped.
+
+use std::io::{self, Write};
+
+use crate::columns::Column;
+use crate::native::{Entry, RecordWriter};
+
+pub struct CsvWriter {
+    columns: Vec<Column>,
+    tabs: bool,
+}
+
+impl CsvWriter {
+    pub fn new(columns: Vec<Column>, tabs: bool) -> Self {
+        CsvWriter { columns, tabs }
+    }
+
+    fn write_row(&self, out: &mut dyn Write, fields: impl Iterator<Item = String>) -> io::Result<()> {
+        let separator = if self.tabs { "\t" } else { "," };
+        let row: Vec<String> = fields
+            .map(|f| if self.tabs { tsv_field(&f) } else { csv_field(&f) })
+            .collect();
+        if self.tabs {
+            writeln!(out, "{}", row.join(separator))
+        } else {
+            write!(out, "{}\r\n", row.join(separator))
+        }
+    }
+}
+
+impl RecordWriter for CsvWriter {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        self.write_row(out, self.columns.iter().map(|c| c.name().to_string()))
+    }
+
+    fn record(&mut self, out: &mut dyn Write, entry: &Entry) -> io::Result<()> {
+        self.write_row(out, self.columns.iter().map(|c| c.value(entry)))
+    }
+
+    fn end(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Quote a CSV field if it needs it, doubling embedded quotes
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape a TSV field
+pub fn tsv_field(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn test_tsv_field() {
+        assert_eq!(tsv_field("a\tb\\c"), "a\\tb\\\\c");
+    }
+
+    #[test]
+    fn test_header_row() {
+        let mut writer = CsvWriter::new(vec![Column::Name, Column::Size], false);
+        let mut out = Vec::new();
+        writer.begin(&mut out).unwrap();
+        assert_eq!(out, b"name,size\r\n");
+
+        let mut writer = CsvWriter::new(vec![Column::Name, Column::Ext], true);
+        let mut out = Vec::new();
+        writer.begin(&mut out).unwrap();
+        assert_eq!(out, b"name\text\n");
+    }
+}
@@ -0,0 +1,261 @@
+//! Shell errors in GNU form
+//!
+//! dir says `File Not Found` without naming the file, and PowerShell writes
+//! multi-line error records. Scripts ported from Linux expect
+//! `ls: cannot access 'x': No such file or directory` and GNU's exit
+//! status: 0 when all is well, 1 for minor trouble such as a subdirectory
+//! that can't be read, 2 for serious trouble such as a missing operand.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::args::LsArgs;
+use crate::execute::Backend;
+use crate::glob;
+use crate::paths::{operand_path, to_windows_path};
+
+/// What cmd prints when a name or path can't be found
+const CMD_NOT_FOUND: [&str; 6] = [
+    "File Not Found",
+    "The system cannot find the file specified.",
+    "The system cannot find the path specified.",
+    "The filename, directory name, or volume label syntax is incorrect.",
+    "The network path was not found.",
+    "The network name cannot be found.",
+];
+
+/// Rewrites a shell's stderr a line at a time and works out the exit status
+pub struct ShellErrors<'a> {
+    args: &'a LsArgs,
+    backend: Backend,
+    status: i32,
+    reported: Vec<usize>, // operands already named in a message
+    in_record: bool,      // inside a PowerShell error record
+    suppressed: bool,     // a "not found" that only meant "nothing to list"
+}
+
+impl<'a> ShellErrors<'a> {
+    pub fn new(args: &'a LsArgs, backend: Backend) -> ShellErrors<'a> {
+        ShellErrors {
+            args,
+            backend,
+            status: 0,
+            reported: Vec::new(),
+            in_record: false,
+            suppressed: false,
+        }
+    }
+
+    /// Pass on one line of stderr, ending included
+    pub fn line(&mut self, err: &mut dyn Write, line: &str) -> io::Result<()> {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let messages = match self.backend {
+            Backend::PowerShell => self.powershell_line(body),
+            _ => self.cmd_line(body),
+        };
+        match messages {
+            Some(messages) => messages.iter().try_for_each(|m| writeln!(err, "ls: {}", m)),
+            None => err.write_all(line.as_bytes()),
+        }
+    }
+
+    /// GNU's exit status, given whether the shell succeeded
+    pub fn exit_code(&self, success: bool) -> i32 {
+        if self.status > 0 {
+            self.status
+        } else if success || self.suppressed {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Messages replacing one line of cmd's stderr, or None to keep it
+    fn cmd_line(&mut self, body: &str) -> Option<Vec<String>> {
+        if CMD_NOT_FOUND.contains(&body.trim()) {
+            let messages = self.missing_operands();
+            if messages.is_empty() {
+                // dir says this of an empty directory too
+                self.suppressed = true;
+            }
+            return Some(messages);
+        }
+        if body.trim() == "Access is denied." {
+            return Some(self.denied(None));
+        }
+        None
+    }
+
+    /// Messages replacing one line of a PowerShell error record: the first
+    /// line carries the message, the rest (position, CategoryInfo) go
+    fn powershell_line(&mut self, body: &str) -> Option<Vec<String>> {
+        let message = ["Get-ChildItem", "Get-Item"].iter().find_map(|command| {
+            let rest = body.strip_prefix(command)?.trim_start();
+            rest.strip_prefix(':').map(str::trim)
+        });
+        let Some(message) = message else {
+            let continues = body.is_empty()
+                || body.starts_with("At line:")
+                || body.starts_with("+ ")
+                || body.starts_with("    + ");
+            return (self.in_record && continues).then(Vec::new);
+        };
+        self.in_record = true;
+
+        if let Some(path) = quoted(message, "Cannot find path '")
+            .or_else(|| quoted(message, "Could not find a part of the path '"))
+            .or_else(|| quoted(message, "Cannot find drive. A drive with the name '"))
+        {
+            self.status = 2;
+            return Some(vec![format!("cannot access '{}': No such file or directory", self.shown(path))]);
+        }
+        if let Some(path) = quoted(message, "Access to the path '") {
+            return Some(self.denied(Some(path)));
+        }
+        self.status = self.status.max(1);
+        Some(vec![message.to_string()])
+    }
+
+    /// Report each operand that doesn't exist, once
+    fn missing_operands(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        for (i, operand) in self.args.paths.iter().enumerate() {
+            if self.reported.contains(&i) || operand_path(operand).symlink_metadata().is_ok() {
+                continue;
+            }
+            // A pattern the shell expanded exists if it matches anything
+            let text = operand.to_string_lossy();
+            if glob::has_wildcards(&text) && glob::expand(&text) != [operand.as_path()] {
+                continue;
+            }
+            self.reported.push(i);
+            self.status = 2;
+            messages.push(format!("cannot access '{}': No such file or directory", text));
+        }
+        messages
+    }
+
+    /// Report a directory that couldn't be read: serious for an operand,
+    /// minor below one
+    fn denied(&mut self, path: Option<&str>) -> Vec<String> {
+        let operand = self.args.paths.iter().position(|operand| {
+            let operand_matches = path.is_none_or(|path| self.is_operand(operand, path));
+            operand_matches
+                && fs::read_dir(operand_path(operand)).is_err_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+        });
+        if let Some(i) = operand {
+            self.status = 2;
+            let shown = self.args.paths[i].to_string_lossy();
+            return vec![format!("cannot open directory '{}': Permission denied", shown)];
+        }
+        self.status = self.status.max(1);
+        match path {
+            Some(path) => vec![format!("cannot open directory '{}': Permission denied", path)],
+            None => vec!["cannot open directory: Permission denied".to_string()],
+        }
+    }
+
+    /// The operand a path in PowerShell's message stands for, as typed, or
+    /// the path itself. PowerShell reports full paths.
+    fn shown(&self, path: &str) -> String {
+        self.args
+            .paths
+            .iter()
+            .find(|operand| self.is_operand(operand, path))
+            .map(|operand| operand.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string())
+    }
+
+    fn is_operand(&self, operand: &Path, path: &str) -> bool {
+        let windows = to_windows_path(&operand.to_string_lossy());
+        let windows = windows.trim_start_matches(".\\").to_lowercase();
+        let path = path.to_lowercase();
+        path == windows || path.ends_with(&format!("\\{}", windows))
+    }
+}
+
+/// The text between `prefix` and the next `'`
+fn quoted<'m>(message: &'m str, prefix: &str) -> Option<&'m str> {
+    let rest = &message[message.find(prefix)? + prefix.len()..];
+    Some(&rest[..rest.find('\'')?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn rewrite<'a>(args: &'a LsArgs, backend: Backend, stderr: &str) -> (String, ShellErrors<'a>) {
+        let mut errors = ShellErrors::new(args, backend);
+        let mut out = Vec::new();
+        for line in stderr.split_inclusive('\n') {
+            errors.line(&mut out, line).unwrap();
+        }
+        (String::from_utf8(out).unwrap(), errors)
+    }
+
+    fn args(paths: &[&str]) -> LsArgs {
+        LsArgs {
+            paths: paths.iter().map(PathBuf::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cmd_not_found() {
+        let args = args(&[".", "no-such-file"]);
+        let (text, errors) = rewrite(&args, Backend::Cmd, "File Not Found\r\nFile Not Found\r\n");
+        assert_eq!(text, "ls: cannot access 'no-such-file': No such file or directory\n");
+        assert_eq!(errors.exit_code(false), 2);
+    }
+
+    #[test]
+    fn test_cmd_empty_directory() {
+        // dir /B of an empty directory fails, where ls just prints nothing
+        let args = args(&["."]);
+        let (text, errors) = rewrite(&args, Backend::Cmd, "File Not Found\r\n");
+        assert_eq!(text, "");
+        assert_eq!(errors.exit_code(false), 0);
+    }
+
+    #[test]
+    fn test_cmd_other_errors() {
+        let args = args(&["."]);
+        let (text, errors) = rewrite(&args, Backend::Cmd, "Access is denied.\r\nsomething else\r\n");
+        assert_eq!(text, "ls: cannot open directory: Permission denied\nsomething else\r\n");
+        assert_eq!(errors.exit_code(false), 1);
+
+        let (_, errors) = rewrite(&args, Backend::Cmd, "");
+        assert_eq!(errors.exit_code(true), 0);
+        assert_eq!(errors.exit_code(false), 1);
+    }
+
+    #[test]
+    fn test_powershell_records() {
+        let args = args(&["./missing"]);
+        let windows_powershell = "Get-ChildItem : Cannot find path 'C:\\work\\missing' because it does not exist.\r\n\
+            At line:1 char:1\r\n\
+            + Get-ChildItem -Path .\\missing\r\n\
+            + ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~\r\n    \
+            + CategoryInfo          : ObjectNotFound: (C:\\work\\missing:String) [Get-ChildItem], ItemNotFoundException\r\n    \
+            + FullyQualifiedErrorId : PathNotFound,Microsoft.PowerShell.Commands.GetChildItemCommand\r\n\
+            \r\n";
+        let (text, errors) = rewrite(&args, Backend::PowerShell, windows_powershell);
+        assert_eq!(text, "ls: cannot access './missing': No such file or directory\n");
+        assert_eq!(errors.exit_code(false), 2);
+
+        // pwsh's concise view
+        let pwsh = "Get-ChildItem: Cannot find path 'D:\\other' because it does not exist.\n";
+        let (text, _) = rewrite(&args, Backend::PowerShell, pwsh);
+        assert_eq!(text, "ls: cannot access 'D:\\other': No such file or directory\n");
+
+        let denied = "Get-ChildItem : Access to the path 'C:\\work\\x\\secret' is denied.\r\nplain line\r\n";
+        let (text, errors) = rewrite(&args, Backend::PowerShell, denied);
+        assert_eq!(
+            text,
+            "ls: cannot open directory 'C:\\work\\x\\secret': Permission denied\nplain line\r\n"
+        );
+        assert_eq!(errors.exit_code(false), 1);
+    }
+}
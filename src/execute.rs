@@ -2,22 +2,49 @@
 //!
 //! Executes translated Windows commands and captures output.
 
+use std::env;
 use std::process::{Command, Stdio};
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
 
 use crate::args::LsArgs;
-use crate::translate::Translation;
+use crate::codepage::{self, CodePage};
+use crate::errors::ShellErrors;
+use crate::gitignore::Excludes;
+use crate::hyperlink;
+use crate::interrupt;
+use crate::native::{self, CommaLine, Entry, EntryKind, RecordWriter};
+use crate::paths::operand_path;
+use crate::quote::Quoting;
+use crate::shells;
+use crate::translate::{dir_bare, names_in_columns, names_listed, names_quotable, ps_bare, translate, Fidelity, Translation};
+use crate::worker::{self, Frame};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Backend {
     Cmd,
     PowerShell,
+    Native,
 }
 
 impl Backend {
     pub fn detect() -> Self {
-        // Default to cmd for speed, PowerShell for complex formatting
-        Backend::Cmd
+        // Default to cmd for speed, PowerShell for complex formatting.
+        // Without Windows shells, only the built-in walker can run.
+        if cfg!(windows) {
+            Backend::Cmd
+        } else {
+            Backend::Native
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Cmd => "cmd.exe",
+            Backend::PowerShell => "PowerShell",
+            Backend::Native => "built-in walker",
+        }
     }
 }
 
@@ -26,50 +53,55 @@ pub struct ExecutionResult {
     pub exit_code: i32,
 }
 
+/// Whether output counts as going to a terminal. --explain and --native
+/// show the command as it would run at one, wherever they're printed.
+pub fn terminal_output(args: &LsArgs) -> bool {
+    args.explain || args.native || io::stdout().is_terminal()
+}
+
 /// Execute the translation and return output
 pub fn execute(args: &LsArgs, translation: &Translation) -> io::Result<ExecutionResult> {
-    // Determine backend
-    let backend = if args.use_powershell {
-        Backend::PowerShell
-    } else if args.use_cmd {
-        Backend::Cmd
-    } else if args.human_readable || args.long_format {
-        // PowerShell handles these better
-        Backend::PowerShell
-    } else {
-        Backend::detect()
-    };
-
-    let (program, cmd_args, command_str) = match backend {
-        Backend::Cmd => (
-            "cmd.exe",
-            vec!["/C", &translation.cmd_command],
-            &translation.cmd_command,
-        ),
-        Backend::PowerShell => (
-            "powershell.exe",
-            vec!["-NoProfile", "-Command", &translation.powershell_command],
-            &translation.powershell_command,
-        ),
-    };
+    let (backend, reason) = choose_backend(args, translation, &system_env);
 
     // If explain mode, just print and don't execute
     if args.explain {
         println!("Command (cmd.exe):    {}", translation.cmd_command);
         println!("Command (PowerShell): {}", translation.powershell_command);
+        if backend == Backend::Native {
+            println!("Command (built-in):   internal directory walker");
+        }
         println!();
         println!("Description: {}", translation.description);
+        println!("Backend:              {} ({})", backend.name(), reason);
+        let interpreter = match backend {
+            Backend::Cmd => Some(shells::cmd()),
+            Backend::PowerShell => Some(shells::powershell()),
+            Backend::Native => None,
+        };
+        if let Some(interpreter) = interpreter {
+            println!("Interpreter:          {} ({})", interpreter.program.display(), interpreter.reason);
+        }
+        for form in &translation.path_forms {
+            println!("Path:                 {}", form);
+        }
+        for warning in &translation.cmd_warnings {
+            println!("Inexact (cmd.exe):    {}", warning);
+        }
+        for warning in &translation.powershell_warnings {
+            println!("Inexact (PowerShell): {}", warning);
+        }
         return Ok(ExecutionResult {
             success: true,
             exit_code: 0,
         });
     }
 
-    // If native mode, just output the command
+    // If native mode, just output the command. The built-in walker has no
+    // command of its own, so print the one Windows would have run.
     if args.native {
-        match backend {
-            Backend::Cmd => println!("{}", translation.cmd_command),
+        match windows_backend(args, translation, &system_env).0 {
             Backend::PowerShell => println!("{}", translation.powershell_command),
+            _ => println!("{}", translation.cmd_command),
         }
         return Ok(ExecutionResult {
             success: true,
@@ -77,38 +109,810 @@ pub fn execute(args: &LsArgs, translation: &Translation) -> io::Result<Execution
         });
     }
 
+    // Say where the shell won't match what ls would have, once for all
+    // the calls it takes. --strict won't run one that drops a flag.
+    let warnings = match backend {
+        Backend::PowerShell => &translation.powershell_warnings,
+        Backend::Cmd => &translation.cmd_warnings,
+        Backend::Native => &Vec::new(),
+    };
+    let unsupported = if backend == Backend::Native { 0 } else { shortfall(translation, backend).0 };
+    if args.strict && unsupported > 0 {
+        // The dropped flags lead the warnings
+        for warning in &warnings[..unsupported] {
+            eprintln!("ls: {}", warning);
+        }
+        eprintln!("ls: --strict: not running without every flag given");
+        return Ok(ExecutionResult {
+            success: false,
+            exit_code: 2,
+        });
+    }
+    if !args.quiet_compat {
+        for warning in warnings {
+            eprintln!("ls: warning: {}", warning);
+        }
+    }
+
+    if args.teach {
+        eprintln!("Backend: {} ({})", backend.name(), reason);
+        if backend != Backend::Native {
+            print_fidelity(translation);
+        }
+    }
+
+    if backend == Backend::Native {
+        if args.teach {
+            eprintln!("Executing: built-in directory walker");
+            eprintln!("---");
+        }
+        let status = native::run(args)?;
+        return Ok(ExecutionResult {
+            success: status == 0,
+            exit_code: status,
+        });
+    }
+
+    // One shell call would merge several operands into a single listing.
+    // Patterns left to the shell can't be told apart beforehand, and
+    // structured records already carry their paths.
+    if args.paths.len() > 1 && !args.shell_glob && !args.output.is_structured() {
+        return run_operands(args, backend);
+    }
+    run_shell(args, translation, backend)
+}
+
+/// List several operands the GNU way: missing ones reported first, then
+/// the files in one listing, then each directory under a `name:` header,
+/// all in ls's sort order
+fn run_operands(args: &LsArgs, backend: Backend) -> io::Result<ExecutionResult> {
+    let (files, dirs, errors) = group_operands(args);
+    for message in &errors {
+        eprintln!("ls: {}", message);
+    }
+    let mut status = if errors.is_empty() { 0 } else { 2 };
+
+    // Each group gets its own shell call, with the operands it covers
+    let run = |entries: &[Entry]| {
+        let args = LsArgs {
+            paths: entries.iter().map(|e| e.path.clone()).collect(),
+            ..args.clone()
+        };
+        run_shell(&args, &translate(&args, terminal_output(&args)), backend)
+    };
+    let finish = |status: i32| {
+        Ok(ExecutionResult {
+            success: status == 0,
+            exit_code: status,
+        })
+    };
+
+    let quoting = Quoting::resolve(args, io::stdout().is_terminal());
+    let eol = if args.zero { "\0" } else { "\n" };
+    let mut printed_any = false;
+    if !files.is_empty() {
+        let result = run(&files)?;
+        if result.exit_code == 130 {
+            return finish(130);
+        }
+        status = status.max(result.exit_code);
+        printed_any = true;
+    }
+    for dir in &dirs {
+        {
+            let mut out = io::stdout().lock();
+            if printed_any {
+                write!(out, "{}", eol)?;
+            }
+            write!(out, "{}:{}", quoting.quote(&dir.name), eol)?;
+            out.flush()?;
+        }
+        let result = run(std::slice::from_ref(dir))?;
+        if result.exit_code == 130 {
+            return finish(130);
+        }
+        status = status.max(result.exit_code);
+        printed_any = true;
+    }
+    finish(status)
+}
+
+/// The operands as ls lists them, files and then directories, each in
+/// sort order, and a message for each one that can't be accessed
+fn group_operands(args: &LsArgs) -> (Vec<Entry>, Vec<Entry>, Vec<String>) {
+    let follow = native::follows_operand_links(args);
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    let mut errors = Vec::new();
+    for operand in &args.paths {
+        let name = operand.to_string_lossy();
+        match Entry::from_path(&operand_path(operand), &name, follow) {
+            Ok(entry) if entry.kind == EntryKind::Dir && !args.directory => dirs.push(entry),
+            Ok(entry) => files.push(entry),
+            Err(e) => errors.push(format!("cannot access '{}': {}", name, native::describe_error(&e))),
+        }
+    }
+    native::sort_entries(&mut files, args);
+    native::sort_entries(&mut dirs, args);
+    (files, dirs, errors)
+}
+
+/// The longest command cmd.exe will run
+const CMD_LIMIT: usize = 8191;
+
+/// The longest command line CreateProcess takes, program name included
+const CREATE_PROCESS_LIMIT: usize = 32767;
+
+/// Run a translated listing in its shell, over as many calls as the
+/// command-line limits need
+fn run_shell(args: &LsArgs, translation: &Translation, backend: Backend) -> io::Result<ExecutionResult> {
+    // PowerShell can read a long command from stdin instead
+    let batches = match backend {
+        Backend::PowerShell => vec![(args.clone(), translation.powershell_command.clone())],
+        _ => batches(args, CMD_LIMIT, |args| translate(args, terminal_output(args)).cmd_command),
+    };
+
+    // One reformatting pass covers every batch, so records from all of
+    // them end up in one JSON array
+    let mut reformat = Reformat::new(args, backend);
+    let mut status = 0;
+    let result = reformat.begin(&mut io::stdout().lock()).and_then(|_| {
+        for (batch, command) in &batches {
+            status = status.max(run_command(batch, backend, command, &mut reformat)?);
+            if status == 130 {
+                break;
+            }
+        }
+        if status != 130 {
+            reformat.end(&mut io::stdout().lock())?;
+        }
+        Ok(())
+    });
+    io::stdout().flush().ok();
+
+    // A closed stdout (ls | head) just means we're done
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+        _ => Ok(ExecutionResult {
+            success: status == 0,
+            exit_code: status,
+        }),
+    }
+}
+
+/// Split the operands, in order, over as many commands as it takes to keep
+/// each within `limit` characters
+fn batches(args: &LsArgs, limit: usize, render: impl Fn(&LsArgs) -> String) -> Vec<(LsArgs, String)> {
+    let command = render(args);
+    if command.len() <= limit || args.paths.len() < 2 {
+        return vec![(args.clone(), command)];
+    }
+
+    // What each operand adds to the command on its own
+    let with_paths = |paths: Vec<PathBuf>| LsArgs { paths, ..args.clone() };
+    let base = render(&with_paths(Vec::new())).len();
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+    let mut length = base;
+    for path in &args.paths {
+        let cost = render(&with_paths(vec![path.clone()])).len().saturating_sub(base);
+        match groups.last_mut() {
+            Some(group) if length + cost <= limit => group.push(path.clone()),
+            _ => {
+                groups.push(vec![path.clone()]);
+                length = base;
+            }
+        }
+        length += cost;
+    }
+
+    groups
+        .into_iter()
+        .map(|paths| {
+            let batch = with_paths(paths);
+            let command = render(&batch);
+            (batch, command)
+        })
+        .collect()
+}
+
+/// Run one command in its shell, passing its output on through `reformat`
+/// as each line arrives. Returns GNU's exit status, or 130 after Ctrl-C.
+fn run_command(args: &LsArgs, backend: Backend, command: &str, reformat: &mut Reformat) -> io::Result<i32> {
+    if backend == Backend::PowerShell && uses_worker(args, &system_env) {
+        match worker::Client::connect() {
+            Ok(client) => return run_on_worker(args, command, client, reformat),
+            Err(e) if args.teach => eprintln!("No PowerShell worker ({}), starting PowerShell", e),
+            Err(_) => {}
+        }
+    }
+
+    // cmd /U makes dir write UTF-16 rather than the console code page
+    let code_page = output_code_page(args, backend);
+    let interpreter = match backend {
+        Backend::PowerShell => shells::powershell(),
+        _ => shells::cmd(),
+    };
+    // "<program>" -NoProfile -Command "...", with its quotes escaped
+    let command_line = interpreter.program.as_os_str().len() + 30 + command.len() + command.matches('"').count();
+    let script = backend == Backend::PowerShell && command_line > CREATE_PROCESS_LIMIT;
+    let program_args = match backend {
+        Backend::PowerShell if script => vec!["-NoProfile", "-Command", "-"],
+        Backend::PowerShell => vec!["-NoProfile", "-Command", command],
+        _ if code_page == CodePage::Utf16 => vec!["/U", "/C", command],
+        _ => vec!["/C", command],
+    };
+
     // If teach mode, print command first
     if args.teach {
-        eprintln!("Executing: {}", command_str);
+        eprintln!("Executing: {}", command);
+        eprintln!("With: {} ({})", interpreter.program.display(), interpreter.reason);
+        if script {
+            eprintln!("(read from stdin: the command line would be too long)");
+        }
+        eprintln!("Decoding output as {}", code_page.name());
         eprintln!("---");
     }
 
-    // Execute the command
-    let output = Command::new(program)
-        .args(&cmd_args)
+    let mut child = Command::new(&interpreter.program)
+        .args(&program_args)
+        .stdin(if script { Stdio::piped() } else { Stdio::inherit() })
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()?;
+        .spawn()?;
+    let watch = interrupt::watch(&child);
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let child_stdin = child.stdin.take();
+    let child_stderr = child.stderr.take().expect("stderr is piped");
+    let child_stdout = child.stdout.take().expect("stdout is piped");
+    let (streamed, status, errors) = thread::scope(|scope| {
+        // The console's input code page would garble names, so the script
+        // goes in as base64 and PowerShell decodes it as UTF-8
+        if let Some(mut stdin) = child_stdin {
+            scope.spawn(move || {
+                let line = format!(
+                    "& ([ScriptBlock]::Create([Text.Encoding]::UTF8.GetString([Convert]::FromBase64String('{}'))))\n",
+                    base64(command.as_bytes())
+                );
+                stdin.write_all(line.as_bytes()).ok();
+            });
+        }
 
-    // Print output
-    if !stdout.is_empty() {
-        print!("{}", stdout);
+        // Errors are rewritten into GNU's wording on the way through
+        let errors = scope.spawn(|| {
+            let mut errors = ShellErrors::new(args, backend);
+            codepage::for_each_line(child_stderr, code_page, |line| {
+                let mut err = io::stderr().lock();
+                errors.line(&mut err, line)?;
+                err.flush()
+            })
+            .ok();
+            errors
+        });
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let streamed = codepage::for_each_line(child_stdout, code_page, |line| reformat.line(&mut out, line));
+        // Nobody reads the pipe once we stop, so stop the shell too
+        if streamed.is_err() {
+            child.kill().ok();
+        }
+        out.flush().ok();
+
+        let status = child.wait();
+        (streamed, status, errors.join().expect("stderr thread panicked"))
+    });
+    let status = status?;
+    drop(watch);
+
+    if interrupt::interrupted() {
+        return Ok(130);
     }
-    if !stderr.is_empty() {
-        eprint!("{}", stderr);
+    streamed?;
+    Ok(errors.exit_code(status.success()))
+}
+
+/// Whether to hand PowerShell scripts to the long-lived worker
+fn uses_worker(args: &LsArgs, env: &dyn Fn(&str) -> Option<String>) -> bool {
+    args.worker || env("LS_WRAPPER_WORKER").is_some_and(|v| v == "1")
+}
+
+/// Look up an environment variable of this process
+fn system_env(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// Run one command on the PowerShell worker, which answers in UTF-8. The
+/// worker outlives any one directory, so it's told ours each time.
+fn run_on_worker(args: &LsArgs, command: &str, client: worker::Client, reformat: &mut Reformat) -> io::Result<i32> {
+    let cwd = env::current_dir()?;
+    if args.teach {
+        eprintln!("Executing: {}", command);
+        eprintln!("(on the PowerShell worker)");
+        eprintln!("---");
     }
 
-    // Flush to ensure output appears
-    io::stdout().flush().ok();
-    io::stderr().flush().ok();
+    let mut errors = ShellErrors::new(args, Backend::PowerShell);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let status = client.run(&cwd.to_string_lossy(), command, &mut |frame| match frame {
+        Frame::Out(line) => reformat.line(&mut out, &format!("{}\n", line)),
+        // Written straight to the console, e.g. --zero's NUL-terminated names
+        Frame::Raw(_) => frame
+            .raw_text()?
+            .split_inclusive('\n')
+            .try_for_each(|line| reformat.line(&mut out, line)),
+        Frame::Err(line) => errors.line(&mut io::stderr().lock(), &format!("{}\n", line)),
+        Frame::Done(_) => Ok(()),
+    });
+    out.flush()?;
+    Ok(errors.exit_code(status? == 0))
+}
+
+/// Standard base64, padded
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decode standard base64, padded or not
+pub fn unbase64(text: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for chunk in text.trim_end_matches('=').as_bytes().chunks(4) {
+        let n = chunk.iter().enumerate().try_fold(0u32, |n, (i, &c)| Some(n | (digit(c)? as u32) << (18 - 6 * i)))?;
+        match chunk.len() {
+            1 => return None,
+            len => bytes.extend_from_slice(&n.to_be_bytes()[1..len]),
+        }
+    }
+    Some(bytes)
+}
 
-    Ok(ExecutionResult {
-        success: output.status.success(),
-        exit_code: output.status.code().unwrap_or(-1),
-    })
+/// The stages a shell's output passes through on its way to stdout, one
+/// line at a time
+struct Reformat<'a> {
+    args: &'a LsArgs,
+    backend: Backend,
+    records: Option<Box<dyn RecordWriter>>, // records for the bare names or paths printed
+    ignored: Option<Excludes>,              // --gitignore on the bare names or paths
+    full_paths: bool,                       // PowerShell: lines are full paths (ps_bare)
+    listed: bool,                           // names come alone, one to a line
+    dir_of: Option<PathBuf>,                // cmd: the directory dir is listing
+    quoting: Option<Quoting>,               // names get quoted
+    link_host: Option<String>,              // cmd: names get hyperlinked
+    name_column: Option<usize>,             // PowerShell: where a table's names start
+    to_nul: bool,                           // --zero: NUL ends each name
+    commas: Option<CommaLine>,              // -m: names joined with commas
+}
+
+impl<'a> Reformat<'a> {
+    fn new(args: &'a LsArgs, backend: Backend) -> Reformat<'a> {
+        let cmd = backend == Backend::Cmd;
+        let full_paths = !cmd && ps_bare(args);
+        let bare = if cmd { dir_bare(args) } else { full_paths };
+        let records = if bare { native::record_writer(args) } else { None };
+        let ignored = (bare && args.gitignore).then(Excludes::default);
+
+        // Names alone on a line or ending a table row can be quoted, but
+        // not ones laid out in columns. PowerShell adds its own links, so
+        // only the text inside them is quoted; bare names are linked here.
+        let tty = io::stdout().is_terminal();
+        let quoting = names_quotable(args, cmd).then(|| Quoting::resolve(args, tty));
+        let link_host = ((cmd || full_paths) && !names_in_columns(args, cmd) && hyperlink::enabled(args, tty))
+            .then(hyperlink::hostname);
+        let listed = names_listed(args, cmd);
+
+        // PowerShell writes NUL terminators itself for bare names; everything
+        // else comes back as lines
+        let nul_terminated = backend == Backend::PowerShell && !args.long_format && !full_paths;
+        let to_nul = args.zero && !nul_terminated;
+
+        Reformat {
+            args,
+            backend,
+            records,
+            ignored,
+            full_paths,
+            listed,
+            dir_of: None,
+            quoting,
+            link_host,
+            name_column: None,
+            to_nul,
+            commas: (listed && args.commas && !args.output.is_structured())
+                .then(|| CommaLine::new(native::terminal_width())),
+        }
+    }
+
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        match &mut self.records {
+            Some(records) => records.begin(out),
+            None => Ok(()),
+        }
+    }
+
+    /// Pass on one line, ending included
+    fn line(&mut self, out: &mut dyn Write, line: &str) -> io::Result<()> {
+        let args = self.args;
+        let body = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[body.len()..];
+        let blank = body.trim().is_empty();
+
+        if self.backend == Backend::Cmd || self.full_paths {
+            let full_paths = self.full_paths;
+            let path = || match full_paths {
+                true => PathBuf::from(body),
+                false => bare_path(body, args),
+            };
+            // The post-filter stage of --gitignore: drop the names git
+            // would ignore
+            if let (Some(ignored), false) = (&mut self.ignored, blank) {
+                if ignored.excludes(&path()) {
+                    return Ok(());
+                }
+            }
+            // dir has no structured output, so for --json or --format=csv
+            // stat each name `dir /B` printed, or path PowerShell did
+            if let Some(records) = &mut self.records {
+                if let Some(entry) = (!blank).then(|| bare_entry(&path(), body, args)).flatten() {
+                    records.record(out, &entry)?;
+                }
+                return Ok(());
+            }
+        }
+
+        let (text, entry) = match (self.backend, blank) {
+            (_, true) => {
+                self.name_column = None;
+                (body.to_string(), false)
+            }
+            (Backend::Cmd, false) => self.dir_line(body),
+            (_, false) => self.powershell_line(body),
+        };
+
+        if let Some(commas) = &mut self.commas {
+            return if entry { commas.name(out, &text) } else { Ok(()) };
+        }
+
+        // --zero: NUL ends each line instead of a newline, headers and
+        // totals included, as the built-in listing does. The shells' blank
+        // lines only space out their layout.
+        if self.to_nul {
+            return if blank { Ok(()) } else { write!(out, "{}\0", text) };
+        }
+        write!(out, "{}{}", text, ending)
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if let Some(commas) = &mut self.commas {
+            commas.end(out)?;
+        }
+        match &mut self.records {
+            Some(records) => records.end(out),
+            None => Ok(()),
+        }
+    }
+
+    /// A line of dir's output with the name on it quoted and hyperlinked,
+    /// and whether it lists an entry
+    fn dir_line(&mut self, body: &str) -> (String, bool) {
+        if self.listed {
+            return (self.show_name(body, || bare_path(body, self.args)), true);
+        }
+        // The long form names each directory in a header before its entries
+        if let Some(dir) = dir_header(body) {
+            self.dir_of = Some(dir);
+            return (body.to_string(), false);
+        }
+        let text = rewrite_link_target(body);
+        let Some(start) = dir_name_start(&text) else {
+            return (text, false);
+        };
+        let (name, target) = split_target(&text[start..]);
+        let path = || match &self.dir_of {
+            Some(dir) => dir.join(name),
+            None => bare_path(name, self.args),
+        };
+        let mut line = format!("{}{}", &text[..start], self.show_name(name, path));
+        if let Some(target) = target {
+            line.push_str(" -> ");
+            match &self.quoting {
+                Some(quoting) => line.push_str(&quoting.quote(target)),
+                None => line.push_str(target),
+            }
+        }
+        (line, true)
+    }
+
+    /// A line of PowerShell's output with the name on it quoted, and
+    /// whether it lists an entry: the whole line with -1, else the Name
+    /// column that ends each table row
+    fn powershell_line(&mut self, body: &str) -> (String, bool) {
+        if self.full_paths {
+            let path = PathBuf::from(body);
+            let name = path.file_name().map_or_else(|| body.into(), |n| n.to_string_lossy());
+            return (self.show_name(&name, || path.clone()), true);
+        }
+        if self.listed {
+            let text = match &self.quoting {
+                Some(quoting) => quote_linked(quoting, body),
+                None => body.to_string(),
+            };
+            return (text, true);
+        }
+        let Some(column) = self.name_column else {
+            // Tables underline their headers, one run of dashes a column
+            if body.contains('-') && body.chars().all(|c| c == '-' || c == ' ') {
+                self.name_column = Some(body.trim_end().rfind(' ').map_or(0, |pos| pos + 1));
+            }
+            return (body.to_string(), false);
+        };
+        match (&self.quoting, body.char_indices().nth(column)) {
+            (Some(quoting), Some((start, _))) => {
+                let (name, target) = split_target(&body[start..]);
+                let mut line = format!("{}{}", &body[..start], quote_linked(quoting, name));
+                if let Some(target) = target {
+                    line.push_str(" -> ");
+                    line.push_str(&quoting.quote(target));
+                }
+                (line, true)
+            }
+            _ => (body.to_string(), true),
+        }
+    }
+
+    /// A name as it's shown: quoted, then linked to its file
+    fn show_name(&self, name: &str, path: impl FnOnce() -> PathBuf) -> String {
+        let shown = match &self.quoting {
+            Some(quoting) => quoting.quote(name),
+            None => name.to_string(),
+        };
+        match &self.link_host {
+            Some(host) => hyperlink::wrap(&shown, &path(), host),
+            None => shown,
+        }
+    }
+}
+
+/// Split `name -> target` as a link is listed
+fn split_target(text: &str) -> (&str, Option<&str>) {
+    match text.split_once(" -> ") {
+        Some((name, target)) => (name, Some(target)),
+        None => (text, None),
+    }
+}
+
+/// Quote a name, inside the hyperlink PowerShell put around it if any
+fn quote_linked(quoting: &Quoting, name: &str) -> String {
+    match hyperlink::unwrap(name) {
+        Some((uri, name)) => hyperlink::wrap_uri(&quoting.quote(name), uri),
+        None => quoting.quote(name),
+    }
+}
+
+/// The directory a header line of dir's long form names, such as
+/// ` Directory of C:\Users\me`. The wording depends on the locale, so look
+/// for an absolute path to a directory at the end instead.
+fn dir_header(line: &str) -> Option<PathBuf> {
+    if !line.starts_with(' ') {
+        return None;
+    }
+    line.match_indices(' ')
+        .map(|(pos, _)| Path::new(&line[pos + 1..]))
+        .find(|path| path.is_absolute() && path.is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// Where the name starts on an entry line of dir's long form. It follows
+/// the date, the time (with AM/PM in some locales) and the size or a tag:
+///
+/// `18/10/2026  13:48    <DIR>          src`
+/// `10/18/2026  01:48 PM          1,234 a b.txt`
+fn dir_name_start(line: &str) -> Option<usize> {
+    if !line.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut starts = Vec::new();
+    let mut previous = ' ';
+    for (pos, c) in line.char_indices() {
+        if c != ' ' && previous == ' ' {
+            starts.push(pos);
+        }
+        previous = c;
+    }
+    let word = |i: usize| starts.get(i).map(|&pos| line[pos..].split(' ').next().unwrap_or(""));
+    let size_or_tag = |word: &str| {
+        (word.starts_with('<') && word.ends_with('>'))
+            || (word.contains(|c: char| c.is_ascii_digit())
+                && word.chars().all(|c| c.is_ascii_digit() || ",.'\u{a0}\u{202f}".contains(c)))
+    };
+    if !word(1)?.contains(':') {
+        return None;
+    }
+    let at = (2..=3).find(|&i| word(i).is_some_and(size_or_tag))?;
+    starts.get(at + 1).copied()
+}
+
+/// The encoding the shell's output will arrive in: --codepage if given,
+/// UTF-16 from cmd /U, else the console's code page
+fn output_code_page(args: &LsArgs, backend: Backend) -> CodePage {
+    match (args.codepage, backend) {
+        (Some(page), _) => page,
+        (None, Backend::Cmd) => CodePage::Utf16,
+        (None, _) => CodePage::active().unwrap_or(CodePage::Utf8),
+    }
+}
+
+/// Whether this invocation runs on the built-in walker rather than a shell
+pub fn uses_builtin(args: &LsArgs) -> bool {
+    builtin_reason(args, &system_env).is_some()
+}
+
+/// Pick the backend for this invocation, and say why
+fn choose_backend(
+    args: &LsArgs,
+    translation: &Translation,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> (Backend, String) {
+    match builtin_reason(args, env) {
+        Some(reason) => (Backend::Native, reason),
+        None => windows_backend(args, translation, env),
+    }
+}
+
+/// Why the built-in walker has to run, if it does
+fn builtin_reason(args: &LsArgs, env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let forced = forced_backend(args, env);
+    if let Some((Backend::Native, reason)) = forced {
+        return Some(reason);
+    }
+    // Only the built-in walker can show git status, or open names that
+    // can't be written into a command line
+    if forced.is_none() {
+        if Backend::detect() == Backend::Native {
+            return Some("no Windows shell on this system".to_string());
+        }
+        if args.git {
+            return Some("--git needs the built-in walker".to_string());
+        }
+        if args.paths.iter().any(|p| p.to_str().is_none()) {
+            return Some("an operand isn't valid Unicode".to_string());
+        }
+    }
+    None
+}
+
+/// The backend a flag asks for, else LS_WRAPPER_BACKEND
+fn forced_backend(args: &LsArgs, env: &dyn Fn(&str) -> Option<String>) -> Option<(Backend, String)> {
+    let flag = |backend, flag: &str| Some((backend, flag.to_string()));
+    if args.use_builtin {
+        flag(Backend::Native, "--builtin")
+    } else if args.use_powershell {
+        flag(Backend::PowerShell, "--powershell")
+    } else if args.use_cmd {
+        flag(Backend::Cmd, "--cmd")
+    } else {
+        shells::backend_override(env)
+    }
+}
+
+/// Pick between the Windows shells, ignoring what this host can run: the
+/// one that carries out the flags given most faithfully, and the one that
+/// starts faster when they tie
+fn windows_backend(
+    args: &LsArgs,
+    translation: &Translation,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> (Backend, String) {
+    match forced_backend(args, env) {
+        Some((Backend::Native, _)) | None => {}
+        Some(forced) => return forced,
+    }
+    let rank = |backend: Backend| (shortfall(translation, backend), startup_ms(args, backend, env));
+    let (best, other) = if rank(Backend::Cmd) <= rank(Backend::PowerShell) {
+        (Backend::Cmd, Backend::PowerShell)
+    } else {
+        (Backend::PowerShell, Backend::Cmd)
+    };
+    let ((unsupported, approximate), ms) = rank(best);
+    let reason = if rank(best).0 == rank(other).0 {
+        format!("as faithful as {} and starts faster, ~{} ms", other.name(), ms)
+    } else {
+        format!("most faithful: {} unsupported, {} approximate", unsupported, approximate)
+    };
+    (best, reason)
+}
+
+/// The flags a shell doesn't carry out, then those it only approximates
+fn shortfall(translation: &Translation, backend: Backend) -> (usize, usize) {
+    let count = |fidelity: Fidelity| {
+        translation
+            .fidelity
+            .iter()
+            .filter(|f| match backend {
+                Backend::PowerShell => f.powershell == fidelity,
+                _ => f.cmd == fidelity,
+            })
+            .count()
+    };
+    (count(Fidelity::Unsupported), count(Fidelity::Approximate))
+}
+
+/// Roughly how long a shell takes to start
+fn startup_ms(args: &LsArgs, backend: Backend, env: &dyn Fn(&str) -> Option<String>) -> u32 {
+    match backend {
+        Backend::Cmd => 30,
+        Backend::PowerShell if uses_worker(args, env) => 50,
+        Backend::PowerShell => 500,
+        Backend::Native => 0,
+    }
+}
+
+/// --teach: how each shell rates on the flags given
+fn print_fidelity(translation: &Translation) {
+    if translation.fidelity.is_empty() {
+        return;
+    }
+    eprintln!("{:<16}{:<14}PowerShell", "Flag", "cmd.exe");
+    for rating in &translation.fidelity {
+        eprintln!("{:<16}{:<14}{}", rating.flag, rating.cmd.name(), rating.powershell.name());
+    }
+}
+
+/// dir shows links as `<JUNCTION>     name [target]`; rewrite the name part
+/// to ls's `name -> target`
+fn rewrite_link_target(line: &str) -> String {
+    let is_link = ["<JUNCTION>", "<SYMLINKD>", "<SYMLINK>", "<MOUNTPT>"]
+        .iter()
+        .any(|tag| line.contains(tag));
+    match line.rfind(" [") {
+        Some(pos) if is_link && line.ends_with(']') => {
+            format!("{} -> {}", &line[..pos], &line[pos + 2..line.len() - 1])
+        }
+        _ => line.to_string(),
+    }
+}
+
+/// The entry for the file a bare line names
+fn bare_entry(path: &Path, line: &str, args: &LsArgs) -> Option<Entry> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| line.to_string());
+    Entry::from_path(path, &name, args.dereference).ok()
+}
+
+/// The file a line of `dir /B` output names: relative to whichever operand
+/// holds it, beside a file operand (dir prints those by name alone), or a
+/// full path with /S
+fn bare_path(line: &str, args: &LsArgs) -> PathBuf {
+    args.paths
+        .iter()
+        .map(Path::new)
+        .flat_map(|base| {
+            let beside = (!base.is_dir()).then(|| base.with_file_name(line));
+            std::iter::once(base.join(line)).chain(beside)
+        })
+        .find(|p| p.symlink_metadata().is_ok())
+        .unwrap_or_else(|| PathBuf::from(line))
 }
 
 /// Print help message
@@ -125,10 +929,16 @@ OPTIONS:
     -A, --almost-all  Show hidden files (excluding . and ..)
     -h, --human-readable  Human-readable file sizes
     -1              One entry per line
+    -C              List entries in columns
+    -x              List entries in columns, filled across
+    -m              List entries separated by commas
     -R, --recursive  List subdirectories recursively
     -d, --directory  List directories themselves, not contents
     -F, --classify  Append indicator (/ for directories)
     -s              Show file size (compatibility flag)
+    -L, --dereference  Follow symbolic links and junctions
+    -H, --dereference-command-line
+                    Follow links named on the command line
 
     -t              Sort by modification time
     -S              Sort by file size
@@ -136,15 +946,74 @@ OPTIONS:
     -U              Do not sort
 
     --color[=WHEN]  Colorize output (always, never, auto)
+    --quoting-style=WORD
+                    Quote names: literal, shell, shell-always,
+                    shell-escape (terminal default), c, escape, locale
+    -Q, --quote-name  Enclose names in double quotes (c style)
+    -b, --escape    Print C-style escapes for special characters
+    -N, --literal   Print names without quoting
+    -q, --hide-control-chars  Print ? instead of control characters
+    --hyperlink[=WHEN]  Hyperlink file names (always, auto, never)
+    --json          Print entries as one JSON array (schema version 1)
+    --ndjson        Print one JSON object per entry, one per line
+    --format=WORD   csv, tsv, json, ndjson, or GNU's across, commas,
+                    horizontal, long, single-column, verbose, vertical
+    --columns=LIST  Columns for csv/tsv: name,size,mtime,ctime,atime,
+                    owner,perms,attrs,ext,path,target,shortname,blocks,git
+                    (default: name,size,mtime,path)
+    --long-columns=SPEC
+                    Layout of -l: a column list as above, or a preset:
+                    gnu, windows, times, full
+    --zero          End each line with NUL, not newline (for xargs -0)
+    --shell-glob    Leave wildcards to dir or PowerShell (faster on huge
+                    directories); --explain shows where they differ
+    --no-glob       Take operands literally; by default on Windows *, ?,
+                    [abc], {{a,b}} and ** are expanded as a Unix shell
+                    would, except after --
+    -I, --ignore=PATTERN  Never list names matching PATTERN
+    --hide=PATTERN  Don't list names matching PATTERN, unless -a or -A
+    --git           With -l, show each entry's git status: index and work
+                    tree letters as in git status (-- unchanged, ?? untracked,
+                    !! ignored); directories sum up their contents
+    --gitignore     Skip files ignored by git (.gitignore, .git/info/exclude,
+                    global excludes), and .git itself
+
+TREE VIEW:
+    --tree          Draw the directory structure (-a, -d for directories
+                    only, -l and -s add sizes and dates, -F, -t/-S/-r)
+    -L N, --level=N  With --tree, descend at most N levels
+    --include=PATTERN  With --tree, only show files matching PATTERN
+    --charset=WORD  Tree lines: unicode (default) or ascii
+    --du            With --tree, show each directory's total size and
+                    file count, including what filters leave off the
+                    tree, quietly (-S sorts by the totals)
+    --tree --json   Write the tree as nested JSON objects
 
 EDUCATIONAL FLAGS:
     --explain       Show Windows translation without executing
     --teach         Execute AND show what command was run
     --native        Output only the Windows command (for scripting)
+    --quiet-compat  Don't warn about flags the shell can't carry out
+    --strict        Fail instead of running without a flag the shell can't do
     --rosetta       Show Unix → Windows command cheatsheet
-    --tree          Tree view of directory structure
-    --powershell    Force PowerShell backend
-    --cmd           Force cmd.exe backend
+    --powershell    Force PowerShell backend (pwsh.exe if on PATH, else
+                    powershell.exe; LS_WRAPPER_POWERSHELL overrides)
+    --cmd           Force cmd.exe backend (%COMSPEC%)
+    --builtin       Force the built-in directory walker
+    --worker        Keep a PowerShell running between calls, so -l and
+                    friends don't wait for it to start (also
+                    LS_WRAPPER_WORKER=1)
+    --codepage=CP   Encoding of the shell's output: 437, 850, 852, 866,
+                    1250, 1251, 1252, utf-8 or utf-16 (default: cmd /U)
+
+ENVIRONMENT:
+    LS_WRAPPER_BACKEND     cmd, powershell or builtin, when no flag picks one
+    LS_WRAPPER_POWERSHELL  The PowerShell to run
+
+EXIT STATUS:
+    0  if OK,
+    1  if minor problems (e.g., cannot access subdirectory),
+    2  if serious trouble (e.g., cannot access command-line argument).
 
 ALIASES:
     ll              Same as ls -l  (rename binary to ll.exe)
@@ -156,6 +1025,7 @@ EXAMPLES:
     ls -la          Long format, show hidden
     ls -lR ./src    Recursive, long format
     ls --explain -la  See how -la translates to Windows
+    ls --tree -L 2 -I target  Two levels deep, skipping target/
 
     ls --native -la   Output: dir /A .
 
@@ -226,3 +1096,291 @@ Tip: Use --explain with any ls command to see its Windows translation!
 "#
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{ColorOption, OutputFormat, QuotingStyle};
+    use crate::testdir::TempDir;
+
+    /// Run output through the Reformat stages, as if `backend` printed it
+    fn reformat(args: &LsArgs, backend: Backend, output: &str) -> String {
+        let mut out = Vec::new();
+        let mut reformat = Reformat::new(args, backend);
+        reformat.begin(&mut out).unwrap();
+        for line in output.split_inclusive('\n') {
+            reformat.line(&mut out, line).unwrap();
+        }
+        reformat.end(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_rewrite_link_targets() {
+        let output = "18/10/2026  13:48    <JUNCTION>     Docs [C:\\Users\\me\\Documents]\r\n\
+                      18/10/2026  13:48    <DIR>          src\r\n";
+        let rewritten = reformat(&LsArgs::default(), Backend::Cmd, output);
+        assert!(rewritten.contains("Docs -> C:\\Users\\me\\Documents\r\n"));
+        assert!(rewritten.contains("<DIR>          src\r\n"));
+        // PowerShell output is left alone
+        assert_eq!(reformat(&LsArgs::default(), Backend::PowerShell, output), output);
+    }
+
+    #[test]
+    fn test_lines_to_nul() {
+        let args = LsArgs {
+            zero: true,
+            one_per_line: true,
+            ..Default::default()
+        };
+        assert_eq!(reformat(&args, Backend::Cmd, "a.txt\r\nb c\r\n\r\n"), "a.txt\0b c\0");
+
+        // Headers and totals end with NUL too, so -lR still says which
+        // directory each entry is in; blank lines go
+        let args = LsArgs {
+            long_format: true,
+            ..args
+        };
+        let long = " Directory of C:\\src\r\n\r\n\
+                    18/10/2026  13:48                 3 a.txt\r\n\
+                    \x20              1 File(s)              3 bytes\r\n";
+        assert_eq!(
+            reformat(&args, Backend::Cmd, long),
+            " Directory of C:\\src\x0018/10/2026  13:48                 3 a.txt\0\
+             \x20              1 File(s)              3 bytes\0"
+        );
+        let table = "\r\n    Directory: C:\\src\r\n\r\nLength Name\r\n------ ----\r\n     3 a.txt\r\n\r\n";
+        assert_eq!(
+            reformat(&args, Backend::PowerShell, table),
+            "    Directory: C:\\src\0Length Name\0------ ----\0     3 a.txt\0"
+        );
+    }
+
+    #[test]
+    fn test_bare_names_to_records() {
+        let dir = TempDir::new("bare");
+        std::fs::write(dir.join("a.txt"), "abc").unwrap();
+
+        let args = LsArgs {
+            output: OutputFormat::Ndjson,
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let json = reformat(&args, Backend::Cmd, "a.txt\r\nmissing\r\n");
+        assert_eq!(json.lines().count(), 1);
+        assert!(json.contains("\"name\":\"a.txt\""));
+        assert!(json.contains("\"size\":3"));
+    }
+
+    #[test]
+    fn test_fidelity_picks_backend() {
+        let backend = |argv: &[&str]| {
+            let args = LsArgs::parse(argv).unwrap();
+            windows_backend(&args, &translate(&args, false), &|_| None).0
+        };
+        assert_eq!(backend(&["ls"]), Backend::Cmd);
+        assert_eq!(backend(&["ls", "-l"]), Backend::PowerShell);
+        // dir's long form lacks modes but lists . and .., -Force the
+        // reverse, so cmd starts faster for as close a result
+        assert_eq!(backend(&["ls", "-la"]), Backend::Cmd);
+        assert_eq!(backend(&["ls", "-I", "*.o"]), Backend::PowerShell);
+        // Neither does -h, so cmd starts faster for the same result
+        assert_eq!(backend(&["ls", "-h"]), Backend::Cmd);
+        // Get-ChildItem -Recurse | Sort-Object mixes the directories
+        assert_eq!(backend(&["ls", "-Rt"]), Backend::Cmd);
+        assert_eq!(backend(&["ls", "-l", "--cmd"]), Backend::Cmd);
+        // dir can't filter its long form
+        assert_eq!(backend(&["ls", "-R", "--gitignore"]), Backend::Cmd);
+        assert_eq!(backend(&["ls", "-lR", "--gitignore"]), Backend::PowerShell);
+
+        // LS_WRAPPER_BACKEND picks when no flag does; the worker makes
+        // PowerShell quicker to start, but not quicker than cmd
+        let args = LsArgs::parse(["ls", "-h"]).unwrap();
+        let env = |name: &str| (name == "LS_WRAPPER_BACKEND").then(|| "powershell".to_string());
+        assert_eq!(windows_backend(&args, &translate(&args, false), &env).0, Backend::PowerShell);
+        let env = |name: &str| (name == "LS_WRAPPER_WORKER").then(|| "1".to_string());
+        assert_eq!(windows_backend(&args, &translate(&args, false), &env).0, Backend::Cmd);
+        assert_eq!(startup_ms(&args, Backend::PowerShell, &env), 50);
+    }
+
+    #[test]
+    fn test_group_operands() {
+        let dir = TempDir::new("operands");
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::write(dir.join("z.txt"), "").unwrap();
+        std::fs::write(dir.join("y.txt"), "").unwrap();
+
+        let mut args = LsArgs {
+            paths: ["b", "z.txt", "missing", "a", "y.txt"].iter().map(|p| dir.join(p)).collect(),
+            ..Default::default()
+        };
+        let names = |entries: &[Entry]| -> Vec<String> {
+            entries.iter().map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+        let (files, dirs, errors) = group_operands(&args);
+        assert_eq!(names(&files), ["y.txt", "z.txt"]);
+        assert_eq!(names(&dirs), ["a", "b"]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with("missing': No such file or directory"));
+
+        // -d lists directories as files; -r reverses both groups
+        args.directory = true;
+        args.reverse = true;
+        let (files, dirs, _) = group_operands(&args);
+        assert_eq!(names(&files), ["z.txt", "y.txt", "b", "a"]);
+        assert!(dirs.is_empty());
+    }
+
+    #[test]
+    fn test_batches() {
+        let args = LsArgs {
+            paths: (0..10).map(|i| PathBuf::from(format!("file{}", i))).collect(),
+            ..Default::default()
+        };
+        let render = |args: &LsArgs| translate(args, false).cmd_command;
+
+        // Short enough for one call
+        let batches = batches(&args, CMD_LIMIT, render);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].1, "dir file0 file1 file2 file3 file4 file5 file6 file7 file8 file9");
+
+        // "dir" plus three " fileN" fit in 21 characters
+        let batches = super::batches(&args, 21, render);
+        let commands: Vec<&str> = batches.iter().map(|(_, c)| c.as_str()).collect();
+        assert_eq!(
+            commands,
+            ["dir file0 file1 file2", "dir file3 file4 file5", "dir file6 file7 file8", "dir file9"]
+        );
+        assert!(batches.iter().all(|(batch, command)| render(batch) == *command));
+
+        // One operand that's too long on its own still gets its call
+        let batches = super::batches(&args, 5, render);
+        assert_eq!(batches.len(), 10);
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("dir é".as_bytes()), "ZGlyIMOp");
+
+        for text in ["", "f", "fo", "foo", "a\0b\0"] {
+            assert_eq!(unbase64(&base64(text.as_bytes())).as_deref(), Some(text.as_bytes()));
+        }
+        assert_eq!(unbase64("Zg").as_deref(), Some(&b"f"[..]));
+        assert_eq!(unbase64("Z"), None);
+        assert_eq!(unbase64("Z!=="), None);
+    }
+
+    #[test]
+    fn test_drop_git_ignored() {
+        let dir = TempDir::new("cmd-git");
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(dir.join("main.rs"), "").unwrap();
+
+        let args = LsArgs {
+            gitignore: true,
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let kept = reformat(&args, Backend::Cmd, ".gitignore\r\nmain.rs\r\ntarget\r\n");
+        assert_eq!(kept, ".gitignore\r\nmain.rs\r\n");
+
+        // PowerShell prints full paths, shown by name
+        let paths: String = [".gitignore", "main.rs", "target"]
+            .iter()
+            .map(|name| format!("{}\r\n", dir.join(name).display()))
+            .collect();
+        assert_eq!(reformat(&args, Backend::PowerShell, &paths), ".gitignore\r\nmain.rs\r\n");
+    }
+
+    #[test]
+    fn test_quotes_bare_names() {
+        let args = LsArgs {
+            one_per_line: true,
+            quoting_style: Some(QuotingStyle::ShellAlways),
+            ..Default::default()
+        };
+        let quoted = reformat(&args, Backend::PowerShell, "a b\r\nc\r\n");
+        assert_eq!(quoted, "'a b'\r\n'c'\r\n");
+    }
+
+    #[test]
+    fn test_quotes_names_ending_lines() {
+        let args = LsArgs {
+            long_format: true,
+            quoting_style: Some(QuotingStyle::ShellAlways),
+            ..Default::default()
+        };
+        let table = "\r\nMode   Length Name\r\n----   ------ ----\r\n-a---       3 a b -> c\r\n\r\n    Directory: x\r\n";
+        let quoted = reformat(&args, Backend::PowerShell, table);
+        assert!(quoted.contains("\r\n-a---       3 'a b' -> 'c'\r\n"));
+        assert!(quoted.contains("\r\nMode   Length Name\r\n"));
+        assert!(quoted.ends_with("\r\n    Directory: x\r\n"));
+
+        let long = " Volume in drive C is OS\r\n18/10/2026  13:48    <SYMLINK>      a b [c]\r\n";
+        let quoted = reformat(&args, Backend::Cmd, long);
+        assert!(quoted.starts_with(" Volume in drive C is OS\r\n"));
+        assert!(quoted.ends_with("<SYMLINK>      'a b' -> 'c'\r\n"));
+    }
+
+    #[test]
+    fn test_joins_names_with_commas() {
+        let args = LsArgs::parse(["ls", "-m", "-N"]).unwrap();
+        assert_eq!(reformat(&args, Backend::Cmd, "a\r\nb c\r\n\r\n"), "a, b c\n");
+        assert_eq!(reformat(&args, Backend::PowerShell, "a\r\nb c\r\n"), "a, b c\n");
+    }
+
+    #[test]
+    fn test_dir_name_start() {
+        fn start(line: &str) -> Option<&str> {
+            dir_name_start(line).map(|pos| &line[pos..])
+        }
+        assert_eq!(start("18/10/2026  13:48    <DIR>          src"), Some("src"));
+        assert_eq!(start("10/18/2026  01:48 PM          1,234 a b.txt"), Some("a b.txt"));
+        assert_eq!(start("18.10.2026  13:48             1.234 7 days.log"), Some("7 days.log"));
+        assert_eq!(start(" Directory of C:\\src"), None);
+        assert_eq!(start("               1 File(s)          1,234 bytes"), None);
+    }
+
+    #[test]
+    fn test_hyperlinks_dir_names() {
+        let dir = TempDir::new("cmd-links");
+        std::fs::write(dir.join("a b.txt"), "").unwrap();
+        let file = dir.join("a b.txt");
+        let uri = |path: &Path| {
+            let absolute = std::fs::canonicalize(path).unwrap();
+            hyperlink::file_uri(&absolute.to_string_lossy(), &hyperlink::hostname())
+        };
+        let linked = |name: &str| hyperlink::wrap_uri(name, &uri(&file));
+
+        // A file operand is printed by its name alone
+        let args = LsArgs {
+            one_per_line: true,
+            hyperlink: Some(ColorOption::Always),
+            paths: vec![file.clone()],
+            ..Default::default()
+        };
+        assert_eq!(reformat(&args, Backend::Cmd, "a b.txt\r\n"), format!("{}\r\n", linked("a b.txt")));
+
+        // The long form's entries are found under its header
+        let args = LsArgs {
+            long_format: true,
+            hyperlink: Some(ColorOption::Always),
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let header = format!(" Directory of {}\r\n", dir.display());
+        let entry = "18/10/2026  13:48                 0 a b.txt\r\n";
+        let output = reformat(&args, Backend::Cmd, &format!("{}\r\n{}", header, entry));
+        assert_eq!(
+            output,
+            format!("{}\r\n18/10/2026  13:48                 0 {}\r\n", header, linked("a b.txt"))
+        );
+    }
+}
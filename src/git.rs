@@ -0,0 +1,205 @@
+//! Git status column (--git)
+//!
+//! Runs `git status --porcelain=v1 -z` once per repository and maps its
+//! two-letter codes onto the entries being listed: the first letter is the
+//! index (staged), the second the work tree. `-` is unchanged, `M`
+//! modified, `A` added, `D` deleted, `R` renamed, `?` untracked and `!`
+//! ignored. A directory shows the most significant status below it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The status of every changed path in one repository
+#[derive(Debug, Default)]
+pub struct GitStatus {
+    root: PathBuf,
+    codes: HashMap<String, [char; 2]>, // keyed by '/'-separated path from the root
+}
+
+impl GitStatus {
+    /// Ask git for the status of the repository at `root`
+    pub fn load(root: &Path) -> Option<GitStatus> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["status", "--porcelain=v1", "-z", "--ignored", "--untracked-files=normal"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(GitStatus {
+            root: root.to_path_buf(),
+            codes: parse_porcelain(&String::from_utf8_lossy(&output.stdout)),
+        })
+    }
+
+    /// The two-letter status of `path`, which must be inside the repository
+    pub fn status(&self, path: &Path, is_dir: bool) -> [char; 2] {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return [' ', ' '];
+        };
+        let relative: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let relative = relative.join("/");
+
+        if let Some(code) = self.codes.get(&relative) {
+            return *code;
+        }
+
+        // git reports wholly untracked or ignored directories as `dir/`
+        let mut prefix = String::new();
+        for part in relative.split('/') {
+            prefix.push_str(part);
+            prefix.push('/');
+            if let Some(code) = self.codes.get(&prefix) {
+                return *code;
+            }
+        }
+
+        if !is_dir {
+            return [' ', ' '];
+        }
+
+        // Anything else below a directory rolls up into it. Ignored files
+        // don't mark their directory, and untracked ones only count
+        // against the work tree.
+        let below = format!("{}/", relative);
+        let mut code = [' ', ' '];
+        for (path, &[x, y]) in &self.codes {
+            if !path.starts_with(&below) || [x, y] == ['!', '!'] {
+                continue;
+            }
+            let x = if x == '?' { ' ' } else { x };
+            code = [more_significant(code[0], x), more_significant(code[1], y)];
+        }
+        code
+    }
+}
+
+/// Loads each repository's status the first time one of its entries is
+/// listed
+#[derive(Debug, Default)]
+pub struct GitCache {
+    repos: HashMap<PathBuf, Option<GitStatus>>,
+}
+
+impl GitCache {
+    /// The status of `path`, or None outside a repository
+    pub fn status(&mut self, path: &Path, is_dir: bool) -> Option<[char; 2]> {
+        let path = std::path::absolute(path).ok()?;
+        let root = repo_root(path.parent()?)?;
+        let repo = self
+            .repos
+            .entry(root.clone())
+            .or_insert_with(|| GitStatus::load(&root));
+        repo.as_ref().map(|repo| repo.status(&path, is_dir))
+    }
+}
+
+/// The closest directory holding `.git`
+fn repo_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|a| a.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Parse `git status --porcelain=v1 -z`: `XY path` records separated by NUL,
+/// where renames and copies are followed by their source path
+fn parse_porcelain(output: &str) -> HashMap<String, [char; 2]> {
+    let mut codes = HashMap::new();
+    let mut fields = output.split('\0');
+    while let Some(field) = fields.next() {
+        let mut chars = field.chars();
+        let (Some(x), Some(y), Some(' ')) = (chars.next(), chars.next(), chars.next()) else {
+            continue;
+        };
+        if x == 'R' || x == 'C' {
+            fields.next();
+        }
+        codes.insert(field[3..].to_string(), [x, y]);
+    }
+    codes
+}
+
+/// Rank status letters so a directory shows its most telling change
+fn more_significant(a: char, b: char) -> char {
+    let rank = |c: char| match c {
+        ' ' => 0,
+        '!' => 1,
+        '?' => 2,
+        'U' => 4,
+        _ => 3,
+    };
+    if rank(b) > rank(a) {
+        b
+    } else {
+        a
+    }
+}
+
+/// Display form of a status: unchanged columns as `-`
+pub fn format(code: [char; 2]) -> String {
+    code.iter().map(|&c| if c == ' ' { '-' } else { c }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TempDir;
+    use std::fs;
+
+    fn git(dir: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .is_ok_and(|o| o.status.success())
+    }
+
+    #[test]
+    fn test_parse_porcelain() {
+        let codes = parse_porcelain(" M a.txt\0R  new.txt\0old.txt\0?? dir/\0");
+        assert_eq!(codes.get("a.txt"), Some(&[' ', 'M']));
+        assert_eq!(codes.get("new.txt"), Some(&['R', ' ']));
+        assert_eq!(codes.get("dir/"), Some(&['?', '?']));
+        assert_eq!(codes.len(), 3);
+    }
+
+    #[test]
+    fn test_status_in_temp_repo() {
+        let dir = TempDir::new("gitstatus");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("new")).unwrap();
+        if !git(&dir, &["init", "-q"]) {
+            return; // no git on this machine
+        }
+
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join("clean.txt"), "a").unwrap();
+        fs::write(dir.join("src/lib.rs"), "a").unwrap();
+        assert!(git(&dir, &["add", "."]));
+        assert!(git(&dir, &["commit", "-q", "-m", "init"]));
+
+        fs::write(dir.join("src/lib.rs"), "b").unwrap();
+        fs::write(dir.join("staged.txt"), "").unwrap();
+        assert!(git(&dir, &["add", "staged.txt"]));
+        fs::write(dir.join("new/file.txt"), "").unwrap();
+        fs::write(dir.join("debug.log"), "").unwrap();
+
+        let mut cache = GitCache::default();
+        let mut status = |name: &str, is_dir: bool| cache.status(&dir.join(name), is_dir).map(format);
+        assert_eq!(status("clean.txt", false).as_deref(), Some("--"));
+        assert_eq!(status("src/lib.rs", false).as_deref(), Some("-M"));
+        assert_eq!(status("src", true).as_deref(), Some("-M"));
+        assert_eq!(status("staged.txt", false).as_deref(), Some("A-"));
+        assert_eq!(status("new", true).as_deref(), Some("??"));
+        assert_eq!(status("new/file.txt", false).as_deref(), Some("??"));
+        assert_eq!(status("debug.log", false).as_deref(), Some("!!"));
+    }
+}
@@ -0,0 +1,308 @@
+//! .gitignore support (--gitignore)
+//!
+//! A small matcher for git's ignore files: the global excludes file,
+//! .git/info/exclude, and .gitignore in each directory from the repository
+//! root down. Handles comments, negation (`!`), directory-only patterns
+//! (trailing `/`), anchoring (a `/` anywhere else) and `**`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob;
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool, // matched against the whole relative path, not the name
+}
+
+impl Rule {
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob::matches_path(&self.pattern, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob::matches(&self.pattern, name)
+        }
+    }
+}
+
+/// The ignore rules in effect for one directory
+#[derive(Debug, Clone, Default)]
+pub struct Gitignore {
+    // Rule sets in increasing precedence, each with the directory its
+    // patterns are relative to
+    sets: Vec<(PathBuf, Vec<Rule>)>,
+}
+
+impl Gitignore {
+    /// The rules that apply to the entries of `dir`
+    pub fn for_dir(dir: &Path) -> Gitignore {
+        let dir = absolute(dir);
+        let root = repo_root(&dir);
+
+        let mut ignore = Gitignore::default();
+        if let Some(file) = global_excludes_file() {
+            ignore.add_file(&root, &file);
+        }
+        ignore.add_file(&root, &root.join(".git").join("info").join("exclude"));
+
+        let mut chain: Vec<&Path> = dir.ancestors().take_while(|a| a.starts_with(&root)).collect();
+        chain.reverse();
+        for each in chain {
+            ignore.add_file(each, &each.join(".gitignore"));
+        }
+        ignore
+    }
+
+    /// The rules for a subdirectory: these, plus its own .gitignore
+    pub fn child(&self, dir: &Path) -> Gitignore {
+        let dir = absolute(dir);
+        let mut ignore = self.clone();
+        ignore.add_file(&dir, &dir.join(".gitignore"));
+        ignore
+    }
+
+    fn add_file(&mut self, base: &Path, file: &Path) {
+        if let Ok(text) = fs::read_to_string(file) {
+            let rules: Vec<Rule> = text.lines().filter_map(parse_rule).collect();
+            if !rules.is_empty() {
+                self.sets.push((base.to_path_buf(), rules));
+            }
+        }
+    }
+
+    /// Whether `path` is ignored. The last matching rule wins, so a later
+    /// `!pattern` brings a file back.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = absolute(path);
+        let mut ignored = false;
+        for (base, rules) in &self.sets {
+            let Ok(relative) = path.strip_prefix(base) else {
+                continue;
+            };
+            let relative: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let relative = relative.join("/");
+            for rule in rules {
+                if rule.matches(&relative, is_dir) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+/// Whether git would ignore paths, or a directory they're in. For output
+/// that arrives as plain paths, such as `dir /S /B`. Each directory's rules
+/// are read once and kept for the paths that follow.
+#[derive(Debug, Default)]
+pub struct Excludes {
+    // The rules for each directory's entries, or None if git ignores the
+    // directory itself
+    dirs: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl Excludes {
+    pub fn excludes(&mut self, path: &Path) -> bool {
+        let path = absolute(path);
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+        self.load(parent);
+        match &self.dirs[parent] {
+            Some(rules) => is_git_dir(&path) || rules.is_ignored(&path, path.is_dir()),
+            None => true,
+        }
+    }
+
+    /// Work out the rules for `dir`, from the repository root down
+    fn load(&mut self, dir: &Path) {
+        if self.dirs.contains_key(dir) {
+            return;
+        }
+        let rules = match dir.parent() {
+            Some(parent) if repo_root(dir) != dir => {
+                self.load(parent);
+                match &self.dirs[parent] {
+                    Some(rules) if !(is_git_dir(dir) || rules.is_ignored(dir, true)) => Some(rules.child(dir)),
+                    _ => None,
+                }
+            }
+            _ => Some(Gitignore::for_dir(dir)),
+        };
+        self.dirs.insert(dir.to_path_buf(), rules);
+    }
+}
+
+fn is_git_dir(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == ".git")
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The closest directory holding `.git`, or `dir` itself outside a repository
+fn repo_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|a| a.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Parse one line of an ignore file
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end_matches('\r');
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // Trailing spaces are dropped unless escaped with a backslash
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+        end -= 1;
+    }
+    let mut line = &line[..end];
+
+    // A leading backslash escapes a literal '!' or '#'
+    let negated = line.starts_with('!');
+    if negated || line.starts_with("\\!") || line.starts_with("\\#") {
+        line = &line[1..];
+    }
+
+    let dir_only = line.ends_with('/');
+    let line = line.trim_end_matches('/');
+    let anchored = line.contains('/');
+    let pattern = line.trim_start_matches('/');
+    if pattern.is_empty() {
+        return None;
+    }
+
+    Some(Rule {
+        pattern: pattern.to_string(),
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+/// core.excludesFile from ~/.gitconfig, else git's default location
+fn global_excludes_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+
+    if let Some(home) = &home {
+        let configured = fs::read_to_string(home.join(".gitconfig"))
+            .ok()
+            .and_then(|config| config_excludes_file(&config));
+        if let Some(path) = configured {
+            return Some(match path.strip_prefix("~/") {
+                Some(rest) => home.join(rest),
+                None => PathBuf::from(path),
+            });
+        }
+    }
+
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.map(|h| h.join(".config")))?;
+    Some(config.join("git").join("ignore"))
+}
+
+/// The excludesFile setting of the [core] section of a git config file
+fn config_excludes_file(config: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut found = None;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_core = line.to_ascii_lowercase().starts_with("[core]");
+        } else if let (true, Some((key, value))) = (in_core, line.split_once('=')) {
+            if key.trim().eq_ignore_ascii_case("excludesfile") {
+                found = Some(value.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TempDir;
+
+    fn repo(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("git-{}", name));
+        fs::create_dir_all(dir.join(".git/info")).unwrap();
+        fs::create_dir_all(dir.join("src/gen")).unwrap();
+        fs::write(dir.join(".gitignore"), "# build output\ntarget/\n*.log\n!keep.log\n/build\ndocs/**/*.tmp\n").unwrap();
+        fs::write(dir.join(".git/info/exclude"), "scratch\n").unwrap();
+        fs::write(dir.join("src/.gitignore"), "gen/\n!important.log\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert!(parse_rule("# comment").is_none());
+        assert!(parse_rule("").is_none());
+
+        let rule = parse_rule("!/out/ ").unwrap();
+        assert!(rule.negated && rule.dir_only && rule.anchored);
+        assert_eq!(rule.pattern, "out");
+
+        assert_eq!(parse_rule("\\#hash").unwrap().pattern, "#hash");
+        assert_eq!(parse_rule("space\\ ").unwrap().pattern, "space\\ ");
+    }
+
+    #[test]
+    fn test_rules() {
+        let dir = repo("rules");
+        let ignore = Gitignore::for_dir(&dir);
+        assert!(ignore.is_ignored(&dir.join("target"), true));
+        assert!(!ignore.is_ignored(&dir.join("target"), false));
+        assert!(ignore.is_ignored(&dir.join("debug.log"), false));
+        assert!(!ignore.is_ignored(&dir.join("keep.log"), false));
+        assert!(ignore.is_ignored(&dir.join("build"), false));
+        assert!(ignore.is_ignored(&dir.join("scratch"), false));
+        assert!(ignore.is_ignored(&dir.join("docs/a/b.tmp"), false));
+        assert!(!ignore.is_ignored(&dir.join("src"), true));
+
+        // Nested .gitignore files add to and override the root's rules
+        let src = ignore.child(&dir.join("src"));
+        assert!(src.is_ignored(&dir.join("src/gen"), true));
+        assert!(!src.is_ignored(&dir.join("src/build"), false));
+        assert!(src.is_ignored(&dir.join("src/x.log"), false));
+        assert!(!src.is_ignored(&dir.join("src/important.log"), false));
+        assert!(Gitignore::for_dir(&dir.join("src")).is_ignored(&dir.join("src/gen"), true));
+    }
+
+    #[test]
+    fn test_excludes_checks_parents() {
+        let dir = repo("excludes");
+        fs::write(dir.join("src/gen/out.rs"), "").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        let mut excludes = Excludes::default();
+        assert!(excludes.excludes(&dir.join("src/gen/out.rs")));
+        assert!(excludes.excludes(&dir.join(".git/info/exclude")));
+        assert!(!excludes.excludes(&dir.join("src/main.rs")));
+        assert!(excludes.excludes(&dir.join("target/debug/ls")));
+        assert!(!excludes.excludes(&dir.join("src/lib.rs")));
+    }
+
+    #[test]
+    fn test_config_excludes_file() {
+        let config = "[user]\n\tname = x\n[core]\n\teditor = vim\n\texcludesFile = ~/.gitignore_global\n";
+        assert_eq!(config_excludes_file(config), Some("~/.gitignore_global".to_string()));
+        assert_eq!(config_excludes_file("[user]\nexcludesfile = x\n"), None);
+    }
+}
@@ -0,0 +1,549 @@
+//! Wildcard matching and expansion
+//!
+//! fnmatch-style patterns: `*`, `?`, `[abc]`, `[a-z]` and `[!abc]`. A
+//! backslash escapes the next character. Matching ignores case on Windows.
+//! Paths are matched a component at a time, where `**` spans any number of
+//! directories.
+//!
+//! cmd.exe hands wildcards to programs unexpanded, so on Windows operands
+//! are expanded here the way a Unix shell would: `{a,b}` alternatives first, then each
+//! component matched against the directory listing, with dotfiles only
+//! matched by a pattern that starts with a dot. A pattern that matches
+//! nothing is kept as it was, so ls can report it missing.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Match a whole file name against a pattern
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(fold_case).collect();
+    let name: Vec<char> = name.chars().map(fold_case).collect();
+    match_from(&pattern, &name)
+}
+
+/// Match a '/'-separated relative path. Wildcards stay within one
+/// component; a `**` component matches zero or more of them.
+pub fn matches_path(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match_components(&pattern, &path)
+}
+
+fn match_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_components(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => matches(first, name) && match_components(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+/// Expand operands that contain wildcards or braces, except the last
+/// `literal` ones, which came after `--`. Names that exist as typed are left
+/// alone, wildcards and all, as are names that aren't valid Unicode.
+pub fn expand_operands(operands: &[PathBuf], literal: usize) -> Vec<PathBuf> {
+    let (patterns, literal) = operands.split_at(operands.len().saturating_sub(literal));
+    patterns
+        .iter()
+        .flat_map(|operand| match operand.to_str() {
+            Some(pattern) if operand.symlink_metadata().is_err() => expand(pattern),
+            _ => vec![operand.clone()],
+        })
+        .chain(literal.iter().cloned())
+        .collect()
+}
+
+/// Expand one pattern into the paths it names, sorted
+pub fn expand(pattern: &str) -> Vec<PathBuf> {
+    let mut results = Vec::new();
+    for alternative in expand_braces(pattern) {
+        // On Windows a backslash separates directories rather than escaping
+        let unixy = if cfg!(windows) {
+            alternative.replace('\\', "/")
+        } else {
+            alternative.clone()
+        };
+        if !has_wildcards(&unixy) {
+            results.push(PathBuf::from(alternative));
+            continue;
+        }
+
+        let (root, rest) = split_root(&unixy);
+        let components: Vec<&str> = rest.split('/').filter(|c| !c.is_empty()).collect();
+        let mut found = Vec::new();
+        walk(PathBuf::from(root), &components, &mut found);
+        if found.is_empty() {
+            results.push(PathBuf::from(alternative));
+        } else {
+            found.sort();
+            found.dedup();
+            results.extend(found);
+        }
+    }
+    results
+}
+
+/// Expand `{a,b}` alternatives, including nested ones, left to right.
+/// Braces without a comma are literal.
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if !cfg!(windows) => i += 2,
+            '{' => {
+                if let Some((close, commas)) = find_brace(&chars, i) {
+                    let prefix: String = chars[..i].iter().collect();
+                    let suffix: String = chars[close + 1..].iter().collect();
+                    let mut bounds = vec![i];
+                    bounds.extend(commas);
+                    bounds.push(close);
+                    return bounds
+                        .windows(2)
+                        .flat_map(|w| {
+                            let alternative: String = chars[w[0] + 1..w[1]].iter().collect();
+                            expand_braces(&format!("{}{}{}", prefix, alternative, suffix))
+                        })
+                        .collect();
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+/// The closing brace and top-level commas of the brace at `open`
+fn find_brace(chars: &[char], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut j = open;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' if !cfg!(windows) => j += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (!commas.is_empty()).then_some((j, commas));
+                }
+            }
+            ',' if depth == 1 => commas.push(j),
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+/// Whether a pattern has an unescaped `*`, `?` or `[`
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !cfg!(windows) => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Split off the part of a path that is never matched: `/` or `C:/`
+fn split_root(pattern: &str) -> (&str, &str) {
+    let bytes = pattern.as_bytes();
+    let len = if pattern.starts_with('/') {
+        1
+    } else if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        if bytes.get(2) == Some(&b'/') {
+            3
+        } else {
+            2
+        }
+    } else {
+        0
+    };
+    pattern.split_at(len)
+}
+
+/// Match `components` below `prefix`, collecting the paths that exist.
+/// Names are matched as Unicode, but found paths keep their exact bytes.
+fn walk(prefix: PathBuf, components: &[&str], found: &mut Vec<PathBuf>) {
+    let Some((&first, rest)) = components.split_first() else {
+        found.push(prefix);
+        return;
+    };
+
+    if !has_wildcards(first) {
+        let path = prefix.join(unescape(first));
+        if (rest.is_empty() && path.symlink_metadata().is_ok()) || path.is_dir() {
+            walk(path, rest, found);
+        }
+        return;
+    }
+
+    let dir = if prefix.as_os_str().is_empty() { Path::new(".") } else { prefix.as_path() };
+    let Ok(read) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut items: Vec<(OsString, bool)> = read
+        .filter_map(Result::ok)
+        .map(|item| {
+            let is_dir = item.file_type().is_ok_and(|t| t.is_dir());
+            (item.file_name(), is_dir)
+        })
+        .filter(|(name, _)| !name.as_encoded_bytes().starts_with(b".") || first.starts_with('.'))
+        .collect();
+    items.sort();
+
+    if first == "**" {
+        // Zero directories, or any number of them. A trailing ** means
+        // everything below, as **/* does.
+        let rest = if rest.is_empty() { &["*"][..] } else { rest };
+        walk(prefix.clone(), rest, found);
+        for (name, _) in items.iter().filter(|(_, is_dir)| *is_dir) {
+            walk(prefix.join(name), components, found);
+        }
+        return;
+    }
+
+    for (name, is_dir) in items {
+        if !matches(first, &name.to_string_lossy()) {
+            continue;
+        }
+        if rest.is_empty() || is_dir {
+            walk(prefix.join(&name), rest, found);
+        }
+    }
+}
+
+/// An unanchored regular expression matching the same '/'-separated paths
+/// as `pattern`, for backends that have regexes but not these wildcards
+pub fn to_regex(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_component = i == 0 || chars[i - 1] == '/';
+        match chars[i] {
+            '*' if at_component && chars.get(i + 1) == Some(&'*') && chars.get(i + 2) == Some(&'/') => {
+                regex.push_str("(.*/)?");
+                i += 3;
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match class_end(&chars, i) {
+                Some(end) => {
+                    regex.push('[');
+                    let mut j = i + 1;
+                    if matches!(chars[j], '!' | '^') {
+                        regex.push('^');
+                        j += 1;
+                    }
+                    for &c in &chars[j..end] {
+                        if matches!(c, '\\' | '[' | ']') {
+                            regex.push('\\');
+                        }
+                        regex.push(c);
+                    }
+                    regex.push(']');
+                    i = end + 1;
+                    continue;
+                }
+                None => regex.push_str("\\["),
+            },
+            '\\' if !cfg!(windows) && i + 1 < chars.len() => {
+                i += 1;
+                push_regex_literal(&mut regex, chars[i]);
+            }
+            c => push_regex_literal(&mut regex, c),
+        }
+        i += 1;
+    }
+    regex
+}
+
+/// Index of the `]` closing the class that opens at `start`
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut j = start + 1;
+    if matches!(chars.get(j), Some('!') | Some('^')) {
+        j += 1;
+    }
+    if chars.get(j) == Some(&']') {
+        j += 1;
+    }
+    (j..chars.len()).find(|&k| chars[k] == ']')
+}
+
+fn push_regex_literal(regex: &mut String, c: char) {
+    if !(c.is_alphanumeric() || matches!(c, '/' | '_' | '-' | ' ' | '~' | ',')) {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+/// Replace bracket classes with `?`: every class, or only negated ones
+pub fn classes_to_any(pattern: &str, negated_only: bool) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut result = String::with_capacity(pattern.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '[' {
+            if let Some(end) = class_end(&chars, i) {
+                let negated = matches!(chars[i + 1], '!' | '^');
+                if negated || !negated_only {
+                    result.push('?');
+                } else {
+                    result.extend(&chars[i..=end]);
+                }
+                i = end + 1;
+                continue;
+            }
+        }
+        result.push(chars[i]);
+        i += 1;
+    }
+    result
+}
+
+/// Drop the backslashes that escape literal characters
+fn unescape(component: &str) -> String {
+    if cfg!(windows) {
+        return component.to_string();
+    }
+    let mut result = String::with_capacity(component.len());
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn fold_case(c: char) -> char {
+    if cfg!(windows) {
+        c.to_ascii_lowercase()
+    } else {
+        c
+    }
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    // Iterative matcher with single-star backtracking
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, next)) = match_class(pattern, p, name[n]) {
+                    if matched {
+                        p = next;
+                        n += 1;
+                        continue;
+                    }
+                } else if name[n] == '[' {
+                    // Unclosed bracket matches itself
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&name[n]) => {
+                p += 2;
+                n += 1;
+                continue;
+            }
+            Some(&c) if c == name[n] => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Mismatch: let the last star swallow one more character
+        match star {
+            Some((star_p, star_n)) => {
+                p = star_p + 1;
+                n = star_n + 1;
+                star = Some((star_p, star_n + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the bracket expression starting at `pattern[start]`.
+/// Returns (matched, index after the closing bracket), or None if the
+/// bracket is never closed.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let lo = *pattern.get(i)?;
+        if lo == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&h| h != ']') {
+            let hi = pattern[i + 2];
+            if lo <= c && c <= hi {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if lo == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TempDir;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("*", ""));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(matches("node_modules", "node_modules"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(matches("[!.]*", "visible"));
+        assert!(!matches("[!.]*", ".hidden"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+    }
+
+    #[test]
+    fn test_braces() {
+        assert_eq!(expand_braces("*.{rs,toml}"), ["*.rs", "*.toml"]);
+        assert_eq!(expand_braces("a{b,c{d,e}}f"), ["abf", "acdf", "acef"]);
+        assert_eq!(expand_braces("{x}{1,2}"), ["{x}1", "{x}2"]);
+        assert_eq!(expand_braces("plain"), ["plain"]);
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = TempDir::new("glob");
+        fs::create_dir_all(dir.join("src/bin")).unwrap();
+        for name in ["Cargo.toml", ".hidden.rs", "src/main.rs", "src/bin/tool.rs", "src/notes.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let base = dir.to_string_lossy().replace('\\', "/");
+        let strip = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|p| p.strip_prefix(&dir).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect()
+        };
+
+        assert_eq!(strip(expand(&format!("{}/*.toml", base))), ["Cargo.toml"]);
+        assert_eq!(
+            strip(expand(&format!("{}/src/*.{{txt,rs}}", base))),
+            ["src/notes.txt", "src/main.rs"]
+        );
+        assert_eq!(strip(expand(&format!("{}/.*.rs", base))), [".hidden.rs"]);
+        assert_eq!(strip(expand(&format!("{}/s?c/[m-n]*", base))), ["src/main.rs", "src/notes.txt"]);
+        assert_eq!(
+            strip(expand(&format!("{}/**/*.rs", base))),
+            ["src/bin/tool.rs", "src/main.rs"]
+        );
+        assert_eq!(
+            strip(expand(&format!("{}/src/**", base))),
+            ["src/bin", "src/bin/tool.rs", "src/main.rs", "src/notes.txt"]
+        );
+
+        // No match: the pattern is kept for ls to report
+        let missing = format!("{}/*.xyz", base);
+        assert_eq!(expand(&missing), vec![PathBuf::from(missing)]);
+
+        // Operands after -- are taken as typed
+        let operands = [format!("{}/*.toml", base), format!("{}/*.toml", base)].map(PathBuf::from);
+        let expanded = expand_operands(&operands, 1);
+        assert_eq!(expanded, [dir.join("Cargo.toml"), operands[1].clone()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand_keeps_invalid_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = TempDir::new("glob-bytes");
+        let name = OsStr::from_bytes(b"caf\xe9.rs");
+        if fs::write(dir.join(name), "").is_err() {
+            return; // the file system insists on UTF-8
+        }
+
+        // The name matches as "caf\u{FFFD}.rs" but comes back byte for byte
+        let pattern = format!("{}/caf?.rs", dir.to_string_lossy());
+        assert_eq!(expand(&pattern), [dir.join(name)]);
+
+        // An operand that isn't Unicode is never a pattern
+        let operand = dir.join(OsStr::from_bytes(b"\xff*"));
+        assert_eq!(expand_operands(std::slice::from_ref(&operand), 0), [operand]);
+    }
+
+    #[test]
+    fn test_to_regex() {
+        assert_eq!(to_regex("*.rs"), "[^/]*\\.rs");
+        assert_eq!(to_regex("src/**/[!a]?.txt"), "src/(.*/)?[^a][^/]\\.txt");
+        assert_eq!(classes_to_any("[ab]x[!c]", true), "[ab]x?");
+        assert_eq!(classes_to_any("[ab]x[!c]", false), "?x?");
+    }
+
+    #[test]
+    fn test_paths() {
+        assert!(matches_path("src/*.rs", "src/main.rs"));
+        assert!(!matches_path("src/*.rs", "src/bin/tool.rs"));
+        assert!(matches_path("src/**/*.rs", "src/main.rs"));
+        assert!(matches_path("src/**/*.rs", "src/bin/tool.rs"));
+        assert!(matches_path("**/target", "a/b/target"));
+        assert!(matches_path("docs/**", "docs/a/b"));
+        assert!(!matches_path("docs/*", "docs/a/b"));
+    }
+}
@@ -0,0 +1,119 @@
+//! OSC 8 terminal hyperlinks (--hyperlink)
+//!
+//! Each name is wrapped in an escape sequence pointing at its file:// URI,
+//! which Windows Terminal, VS Code and most Linux terminals make clickable.
+
+use std::fs;
+use std::path::Path;
+
+use crate::args::{ColorOption, LsArgs};
+
+/// Whether names should be hyperlinked for this invocation
+pub fn enabled(args: &LsArgs, tty: bool) -> bool {
+    match args.hyperlink {
+        Some(ColorOption::Always) => true,
+        Some(ColorOption::Auto) => tty,
+        Some(ColorOption::Never) | None => false,
+    }
+}
+
+/// Host name for the URI authority, as GNU ls puts it there
+pub fn hostname() -> String {
+    if let Ok(name) = std::env::var("COMPUTERNAME") {
+        return name;
+    }
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Wrap an already-formatted name in an OSC 8 link to `path`
+pub fn wrap(display: &str, path: &Path, host: &str) -> String {
+    let absolute = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let uri = file_uri(&absolute.to_string_lossy(), host);
+    wrap_uri(display, &uri)
+}
+
+/// Wrap text in an OSC 8 link to `uri`
+pub fn wrap_uri(display: &str, uri: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", uri, display)
+}
+
+/// Split a line that is exactly one OSC 8 link into (uri, text)
+pub fn unwrap(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("\x1b]8;;")?;
+    let (uri, rest) = rest.split_once("\x1b\\")?;
+    let text = rest.strip_suffix("\x1b]8;;\x1b\\")?;
+    Some((uri, text))
+}
+
+/// Build a file:// URI from an absolute Windows or Unix path.
+///
+/// `C:\Users\me\a b.txt`  -> `file://HOST/C:/Users/me/a%20b.txt`
+/// `\\server\share\x`     -> `file://server/share/x`
+/// `\\?\C:\long\path`     -> `file://HOST/C:/long/path`
+/// `/home/me/x`           -> `file://HOST/home/me/x`
+pub fn file_uri(path: &str, host: &str) -> String {
+    let path = path.replace('\\', "/");
+
+    // Extended-length prefixes from canonicalize() on Windows
+    let path = if let Some(rest) = path.strip_prefix("//?/UNC/") {
+        format!("//{}", rest)
+    } else if let Some(rest) = path.strip_prefix("//?/") {
+        rest.to_string()
+    } else {
+        path
+    };
+
+    let (authority, path) = match path.strip_prefix("//") {
+        Some(unc) => match unc.find('/') {
+            Some(pos) => (unc[..pos].to_string(), unc[pos..].to_string()),
+            None => (unc.to_string(), "/".to_string()),
+        },
+        None if path.starts_with('/') => (host.to_string(), path),
+        None => (host.to_string(), format!("/{}", path)),
+    };
+
+    format!("file://{}{}", authority, percent_encode(&path))
+}
+
+/// Percent-encode everything but unreserved characters, '/' and ':'
+fn percent_encode(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_uri() {
+        assert_eq!(
+            file_uri(r"C:\Users\me\a b.txt", "PC"),
+            "file://PC/C:/Users/me/a%20b.txt"
+        );
+        assert_eq!(file_uri(r"\\server\share\x#1", "PC"), "file://server/share/x%231");
+        assert_eq!(file_uri(r"\\?\C:\long\path", "PC"), "file://PC/C:/long/path");
+        assert_eq!(file_uri(r"\\?\UNC\server\share", "PC"), "file://server/share");
+        assert_eq!(file_uri("/home/me/caf\u{e9}", "box"), "file://box/home/me/caf%C3%A9");
+    }
+
+    #[test]
+    fn test_unwrap() {
+        let line = wrap_uri("a b", "file://PC/C:/a%20b");
+        assert_eq!(unwrap(&line), Some(("file://PC/C:/a%20b", "a b")));
+        assert_eq!(unwrap("plain"), None);
+    }
+}
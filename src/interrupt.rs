@@ -0,0 +1,201 @@
+//! Ctrl-C while a shell runs
+//!
+//! The shell shares our console, so it sees Ctrl-C too, but it may not stop
+//! promptly and we'd otherwise die with its output half written. While a
+//! `Watch` is held, Ctrl-C instead terminates the child, and once its pipes
+//! close the caller checks `interrupted()` and exits with status 130, as a
+//! shell reports a command killed by SIGINT. Dropping the `Watch` puts
+//! back whatever handled Ctrl-C before.
+
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CHILD: AtomicUsize = AtomicUsize::new(0); // process handle or pid; 0 for none
+
+/// Whether Ctrl-C was pressed since `watch` was called
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Terminates `child` on Ctrl-C until dropped
+pub struct Watch;
+
+pub fn watch(child: &Child) -> Watch {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    CHILD.store(sys::id(child), Ordering::SeqCst);
+    sys::install();
+    Watch
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        CHILD.store(0, Ordering::SeqCst);
+        sys::uninstall();
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::{CHILD, INTERRUPTED};
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use std::sync::atomic::Ordering;
+
+    type Handler = unsafe extern "system" fn(u32) -> i32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<Handler>, add: i32) -> i32;
+        fn TerminateProcess(process: *mut std::ffi::c_void, exit_code: u32) -> i32;
+    }
+
+    const CTRL_C_EVENT: u32 = 0;
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    pub fn id(child: &Child) -> usize {
+        child.as_raw_handle() as usize
+    }
+
+    unsafe extern "system" fn on_ctrl(event: u32) -> i32 {
+        if event != CTRL_C_EVENT && event != CTRL_BREAK_EVENT {
+            return 0;
+        }
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        let handle = CHILD.load(Ordering::SeqCst);
+        if handle != 0 {
+            // SAFETY: the handle belongs to a Child that outlives the Watch
+            unsafe { TerminateProcess(handle as *mut std::ffi::c_void, 130) };
+        }
+        1
+    }
+
+    pub fn install() {
+        // SAFETY: on_ctrl only touches atomics and the child's handle
+        unsafe { SetConsoleCtrlHandler(Some(on_ctrl), 1) };
+    }
+
+    pub fn uninstall() {
+        // SAFETY: removes the handler added by install
+        unsafe { SetConsoleCtrlHandler(Some(on_ctrl), 0) };
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::{CHILD, INTERRUPTED};
+    use std::ffi::c_int;
+    use std::process::Child;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+        fn kill(pid: c_int, sig: c_int) -> c_int;
+    }
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    const SIG_IGN: usize = 1;
+    const SIG_ERR: usize = usize::MAX;
+
+    /// The SIGINT disposition install replaced; SIG_ERR for none
+    static PREVIOUS: AtomicUsize = AtomicUsize::new(SIG_ERR);
+
+    pub fn id(child: &Child) -> usize {
+        child.id() as usize
+    }
+
+    extern "C" fn on_sigint(_: c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        let pid = CHILD.load(Ordering::SeqCst);
+        if pid != 0 {
+            // SAFETY: kill is async-signal-safe
+            unsafe { kill(pid as c_int, SIGTERM) };
+        }
+    }
+
+    pub fn install() {
+        // SAFETY: on_sigint only touches atomics and calls kill
+        let previous = unsafe { signal(SIGINT, on_sigint as extern "C" fn(c_int) as usize) };
+        // Whoever started us with SIGINT ignored, as nohup and background
+        // jobs do, meant Ctrl-C to pass us by
+        if previous == SIG_IGN {
+            // SAFETY: puts back the disposition we just replaced
+            unsafe { signal(SIGINT, SIG_IGN) };
+        }
+        PREVIOUS.store(previous, Ordering::SeqCst);
+    }
+
+    pub fn uninstall() {
+        let previous = PREVIOUS.swap(SIG_ERR, Ordering::SeqCst);
+        if previous != SIG_ERR {
+            // SAFETY: restores the disposition install replaced
+            unsafe { signal(SIGINT, previous) };
+        }
+    }
+}
+
+#[cfg(not(any(windows, unix)))]
+mod sys {
+    use std::process::Child;
+
+    pub fn id(child: &Child) -> usize {
+        child.id() as usize
+    }
+
+    pub fn install() {}
+
+    pub fn uninstall() {}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    extern "C" {
+        fn raise(sig: std::ffi::c_int) -> std::ffi::c_int;
+        fn signal(signum: std::ffi::c_int, handler: usize) -> usize;
+    }
+
+    /// Puts back a SIGINT disposition when dropped, even by a failing
+    /// assertion, so the rest of the test binary isn't left ignoring it
+    struct Restore(usize);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            // SAFETY: a disposition signal() returned earlier
+            unsafe { signal(2, self.0) };
+        }
+    }
+
+    #[test]
+    fn test_ctrl_c_terminates_child() {
+        let Ok(mut child) = Command::new("sleep").arg("10").spawn() else {
+            return; // no sleep on this machine
+        };
+        let started = Instant::now();
+        let watch = watch(&child);
+        // SAFETY: the handler installed by watch() catches it
+        unsafe { raise(2) };
+        let status = child.wait().unwrap();
+        drop(watch);
+
+        assert!(interrupted());
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // SIGINT left ignored stays ignored, and is ignored again after
+        // the watch ends
+        // SAFETY: SIG_IGN (1) is a valid disposition
+        let _restore = Restore(unsafe { signal(2, 1) });
+        let ignoring = super::watch(&child);
+        // SAFETY: ignored
+        unsafe { raise(2) };
+        drop(ignoring);
+        assert!(!interrupted());
+        // SAFETY: as above
+        assert_eq!(unsafe { signal(2, 1) }, 1);
+    }
+}
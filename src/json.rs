@@ -0,0 +1,265 @@
+//! Machine-readable output: --json and --ndjson
+//!
+//! Schema version 1. Each entry is an object with these keys, in this order:
+//!
+//! | key           | type            | notes                                      |
+//! |---------------|-----------------|--------------------------------------------|
+//! | `version`     | number          | always 1 for this schema                   |
+//! | `name`        | string          | file name as listed                        |
+//! | `path`        | string          | absolute path, links not resolved          |
+//! | `type`        | string          | file, directory, symlink, fifo, socket, device |
+//! | `size`        | number          | bytes                                      |
+//! | `modified`    | string or null  | RFC 3339, UTC, whole seconds               |
+//! | `accessed`    | string or null  | as above                                   |
+//! | `created`     | string or null  | as above; null where the OS doesn't record it |
+//! | `attributes`  | array of string | lower-case .NET FileAttributes names       |
+//! | `link_target` | string or null  | target of a link that wasn't followed      |
+//! | `hidden`      | bool            | dotfile or Hidden attribute                |
+//!
+//! --json prints one array of these; --ndjson prints one object per line as
+//! entries are found. With --tree, each operand is one object whose
+//! directories gain a `children` array, and with --du also `total_size`
+//! (bytes of all files below, filtered off the tree or not) and
+//! `file_count`. New keys may be added without bumping the version;
+//! removing or changing one bumps it.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::native::{civil_from_days, unix_seconds, Entry, EntryKind, RecordWriter};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Windows attribute bits and their .NET names, as PowerShell prints them
+const ATTRIBUTES: [(u32, &str); 14] = [
+    (0x1, "readonly"),
+    (0x2, "hidden"),
+    (0x4, "system"),
+    (0x10, "directory"),
+    (0x20, "archive"),
+    (0x40, "device"),
+    (0x80, "normal"),
+    (0x100, "temporary"),
+    (0x200, "sparsefile"),
+    (0x400, "reparsepoint"),
+    (0x800, "compressed"),
+    (0x1000, "offline"),
+    (0x2000, "notcontentindexed"),
+    (0x4000, "encrypted"),
+];
+
+/// Writes entries as a JSON array or as NDJSON lines
+pub struct JsonWriter {
+    lines: bool,
+    count: usize,
+}
+
+impl JsonWriter {
+    pub fn new(lines: bool) -> Self {
+        JsonWriter { lines, count: 0 }
+    }
+}
+
+impl RecordWriter for JsonWriter {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if !self.lines {
+            write!(out, "[")?;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, out: &mut dyn Write, entry: &Entry) -> io::Result<()> {
+        if self.lines {
+            writeln!(out, "{}", entry_object(entry))?;
+            // Consumers read NDJSON as a stream
+            out.flush()?;
+        } else {
+            let separator = if self.count == 0 { "\n" } else { ",\n" };
+            write!(out, "{}{}", separator, entry_object(entry))?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if !self.lines {
+            let separator = if self.count == 0 { "" } else { "\n" };
+            writeln!(out, "{}]", separator)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialize one entry as a single-line JSON object
+pub fn entry_object(entry: &Entry) -> String {
+    let attributes: Vec<String> = attribute_names(entry).iter().map(|a| string(a)).collect();
+    let link_target = match &entry.link_target {
+        Some(target) => string(&target.to_string_lossy()),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\"version\":{},\"name\":{},\"path\":{},\"type\":\"{}\",\"size\":{},\
+         \"modified\":{},\"accessed\":{},\"created\":{},\"attributes\":[{}],\
+         \"link_target\":{},\"hidden\":{}}}",
+        SCHEMA_VERSION,
+        string(&entry.name),
+        string(&absolute_path(&entry.path).to_string_lossy()),
+        type_name(entry.kind),
+        entry.size,
+        timestamp(entry.modified),
+        timestamp(entry.accessed),
+        timestamp(entry.created),
+        attributes.join(","),
+        link_target,
+        entry.hidden,
+    )
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub fn type_name(kind: EntryKind) -> &'static str {
+    match kind {
+        EntryKind::File => "file",
+        EntryKind::Dir => "directory",
+        EntryKind::Symlink => "symlink",
+        EntryKind::Fifo => "fifo",
+        EntryKind::Socket => "socket",
+        EntryKind::Device => "device",
+    }
+}
+
+/// Attribute names from the Windows attribute bits, or the closest
+/// equivalents on systems that don't have them
+pub fn attribute_names(entry: &Entry) -> Vec<&'static str> {
+    match entry.attributes {
+        Some(bits) => ATTRIBUTES
+            .iter()
+            .filter(|(bit, _)| bits & bit != 0)
+            .map(|(_, name)| *name)
+            .collect(),
+        None => {
+            let mut names = Vec::new();
+            if entry.readonly {
+                names.push("readonly");
+            }
+            if entry.hidden {
+                names.push("hidden");
+            }
+            if entry.kind == EntryKind::Dir {
+                names.push("directory");
+            }
+            if entry.kind == EntryKind::Symlink {
+                names.push("reparsepoint");
+            }
+            names
+        }
+    }
+}
+
+/// RFC 3339 UTC timestamp, or null
+pub fn timestamp(time: Option<SystemTime>) -> String {
+    match time {
+        Some(time) => format!("\"{}\"", iso_time(time)),
+        None => "null".to_string(),
+    }
+}
+
+/// Format as `2026-10-18T13:48:04Z`
+pub fn iso_time(time: SystemTime) -> String {
+    let secs = unix_seconds(time);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day % 3600 / 60,
+        of_day % 60
+    )
+}
+
+/// Quote and escape a JSON string
+pub fn string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn sample() -> Entry {
+        Entry {
+            name: "a \"b\".txt".to_string(),
+            path: PathBuf::from(r"C:\dir\a.txt"),
+            kind: EntryKind::File,
+            size: 42,
+            blocks: 1,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_760_795_284)),
+            accessed: None,
+            created: None,
+            changed: None,
+            hidden: false,
+            readonly: true,
+            mode: None,
+            attributes: Some(0x21),
+            uid: None,
+            link_target: None,
+            target_kind: None,
+            git_status: None,
+        }
+    }
+
+    #[test]
+    fn test_entry_object() {
+        let json = entry_object(&sample());
+        assert!(json.starts_with("{\"version\":1,\"name\":\"a \\\"b\\\".txt\""));
+        assert!(json.contains("a.txt\",\"type\":\"file\""));
+        assert!(json.contains("\"modified\":\"2025-10-18T13:48:04Z\""));
+        assert!(json.contains("\"accessed\":null"));
+        assert!(json.contains("\"attributes\":[\"readonly\",\"archive\"]"));
+        assert!(json.ends_with("\"link_target\":null,\"hidden\":false}"));
+    }
+
+    #[test]
+    fn test_writer_framing() {
+        let mut out = Vec::new();
+        let mut writer = JsonWriter::new(false);
+        writer.begin(&mut out).unwrap();
+        writer.end(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[]\n");
+
+        let mut out = Vec::new();
+        let mut writer = JsonWriter::new(true);
+        writer.begin(&mut out).unwrap();
+        writer.record(&mut out, &sample()).unwrap();
+        writer.record(&mut out, &sample()).unwrap();
+        writer.end(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_string_escapes_controls() {
+        assert_eq!(string("a\u{1}b"), "\"a\\u0001b\"");
+    }
+}
@@ -4,20 +4,38 @@
 //! Tiny, fast, educational.
 
 mod args;
+mod codepage;
+mod columns;
+mod csv;
+mod errors;
 mod execute;
+mod git;
+mod gitignore;
+mod glob;
+mod hyperlink;
+mod interrupt;
+mod json;
+mod native;
+mod paths;
+mod quote;
+mod shells;
+#[cfg(test)]
+mod testdir;
 mod translate;
+mod tree;
+mod worker;
 
 use std::env;
-use std::process::{Command, ExitCode, Stdio};
+use std::ffi::{OsStr, OsString};
+use std::process::ExitCode;
 use std::path::Path;
-use std::io::{self, Write};
 
 use args::LsArgs;
-use execute::{execute, print_help, print_version, print_rosetta};
+use execute::{execute, print_help, print_rosetta, print_version, terminal_output, uses_builtin};
 use translate::translate;
 
 /// Detect alias from program name (ll, la, l)
-fn get_alias_flags(program_name: &str) -> Option<&'static str> {
+fn get_alias_flags(program_name: &OsStr) -> Option<&'static str> {
     let name = Path::new(program_name)
         .file_stem()
         .and_then(|s| s.to_str())
@@ -31,22 +49,40 @@ fn get_alias_flags(program_name: &str) -> Option<&'static str> {
     }
 }
 
+/// GNU ls exits 0, 1 (minor trouble) or 2 (serious trouble); anything
+/// that doesn't fit counts as serious
+fn exit_code(status: i32) -> ExitCode {
+    ExitCode::from(u8::try_from(status).unwrap_or(2))
+}
+
 fn main() -> ExitCode {
-    // Get command line arguments
-    let mut args: Vec<String> = env::args().collect();
+    // Get command line arguments. Paths may not be valid Unicode, so they
+    // stay OsStrings.
+    let mut args: Vec<OsString> = env::args_os().collect();
 
     // Check for alias (ll, la, l)
     if let Some(alias_flags) = args.first().and_then(|s| get_alias_flags(s)) {
         // Insert the alias flags after program name
-        args.insert(1, alias_flags.to_string());
+        args.insert(1, OsString::from(alias_flags));
+    }
+
+    // ls --worker-host keeps a PowerShell running for later calls
+    if args.get(1).is_some_and(|arg| arg == "--worker-host") {
+        return match worker::host() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("ls: worker host error: {}", e);
+                ExitCode::from(2)
+            }
+        };
     }
 
-    let ls_args = match LsArgs::parse(&args) {
+    let mut ls_args = match LsArgs::parse(&args) {
         Ok(args) => args,
         Err(e) => {
-            eprintln!("ls-wrapper: {}", e);
+            eprintln!("ls: {}", e);
             eprintln!("Try 'ls --help' for more information.");
-            return ExitCode::from(1);
+            return ExitCode::from(2);
         }
     };
 
@@ -67,13 +103,28 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS;
     }
 
-    // Handle --tree (run Windows tree command)
+    // cmd.exe leaves wildcards to the program, so expand them here, the
+    // same way whichever backend runs. With --shell-glob a Windows shell
+    // gets them instead; the built-in walker and --tree still need us.
+    // Elsewhere the shell has expanded them already.
+    let shell_globs = ls_args.shell_glob && !ls_args.tree && !uses_builtin(&ls_args);
+    if cfg!(windows) && !ls_args.no_glob && !shell_globs {
+        ls_args.paths = glob::expand_operands(&ls_args.paths, ls_args.after_dashes);
+    }
+
+    // Handle --tree with the built-in tree renderer
     if ls_args.tree {
-        return run_tree(&ls_args);
+        return match tree::run(&ls_args) {
+            Ok(status) => exit_code(status),
+            Err(e) => {
+                eprintln!("ls: tree error: {}", e);
+                ExitCode::from(2)
+            }
+        };
     }
 
     // Translate ls arguments to Windows commands
-    let translation = translate(&ls_args);
+    let translation = translate(&ls_args, terminal_output(&ls_args));
 
     // Execute the translation
     match execute(&ls_args, &translation) {
@@ -81,40 +132,12 @@ fn main() -> ExitCode {
             if result.success {
                 ExitCode::SUCCESS
             } else {
-                ExitCode::from(result.exit_code as u8)
-            }
-        }
-        Err(e) => {
-            eprintln!("ls-wrapper: execution error: {}", e);
-            ExitCode::from(1)
-        }
-    }
-}
-
-/// Run the Windows tree command
-fn run_tree(args: &LsArgs) -> ExitCode {
-    let path = args.paths.first().map(|s| s.as_str()).unwrap_or(".");
-
-    // tree /F shows files, tree /A uses ASCII
-    let output = Command::new("cmd.exe")
-        .args(["/C", "tree", "/F", path])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output();
-
-    match output {
-        Ok(output) => {
-            io::stdout().write_all(&output.stdout).ok();
-            io::stderr().write_all(&output.stderr).ok();
-            if output.status.success() {
-                ExitCode::SUCCESS
-            } else {
-                ExitCode::from(1)
+                exit_code(result.exit_code)
             }
         }
         Err(e) => {
-            eprintln!("ls-wrapper: failed to run tree: {}", e);
-            ExitCode::from(1)
+            eprintln!("ls: execution error: {}", e);
+            ExitCode::from(2)
         }
     }
 }
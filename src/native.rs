@@ -0,0 +1,1313 @@
+//! Built-in listing backend
+//!
+//! Walks directories with std::fs and renders ls output directly, without
+//! shelling out to cmd.exe or PowerShell. Used off Windows, or when forced
+//! with --builtin.
+
+use std::fs::{self, Metadata};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::args::{LsArgs, OutputFormat};
+use crate::columns::Column;
+use crate::csv::CsvWriter;
+use crate::git::GitCache;
+use crate::gitignore::Gitignore;
+use crate::glob;
+use crate::hyperlink;
+use crate::json::JsonWriter;
+use crate::paths::operand_path;
+use crate::quote::Quoting;
+
+/// What kind of file an entry is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    Device,
+}
+
+/// A single file system entry, as the renderer sees it
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub blocks: u64,                  // 1K blocks
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    pub changed: Option<SystemTime>,  // ctime: inode change on Unix, creation on Windows
+    pub hidden: bool,
+    pub readonly: bool,
+    pub mode: Option<u32>,            // Unix permission bits, where available
+    pub attributes: Option<u32>,      // Windows file attributes, where available
+    pub uid: Option<u32>,             // owner's user id, where available
+    pub link_target: Option<PathBuf>, // set for links that were not followed
+    pub target_kind: Option<EntryKind>, // kind of what a link points to (None if broken)
+    pub git_status: Option<[char; 2]>,  // --git, filled in by the lister
+}
+
+impl Entry {
+    /// Stat `path`, following a symbolic link (or junction) if `follow` is set.
+    /// A broken link is listed as the link itself, even when following.
+    pub fn from_path(path: &Path, name: &str, follow: bool) -> io::Result<Entry> {
+        let lmeta = fs::symlink_metadata(path)?;
+        let is_link = lmeta.file_type().is_symlink();
+        let followed = if is_link { fs::metadata(path).ok() } else { None };
+        let target_kind = followed.as_ref().map(kind_of);
+
+        let (meta, link_target) = match (&followed, follow) {
+            (Some(meta), true) => (meta, None),
+            _ if is_link => (&lmeta, fs::read_link(path).ok()),
+            _ => (&lmeta, None),
+        };
+
+        Ok(Entry {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            kind: kind_of(meta),
+            size: meta.len(),
+            blocks: block_count(meta),
+            modified: meta.modified().ok(),
+            accessed: meta.accessed().ok(),
+            created: meta.created().ok(),
+            changed: change_time(meta),
+            hidden: (name.starts_with('.') && name != "." && name != "..")
+                || has_hidden_attribute(meta),
+            readonly: meta.permissions().readonly(),
+            mode: unix_mode(meta),
+            attributes: windows_attributes(meta),
+            uid: unix_uid(meta),
+            link_target,
+            target_kind,
+            git_status: None,
+        })
+    }
+}
+
+fn kind_of(meta: &Metadata) -> EntryKind {
+    let ft = meta.file_type();
+    if ft.is_symlink() {
+        return EntryKind::Symlink;
+    }
+    if ft.is_dir() {
+        return EntryKind::Dir;
+    }
+    if ft.is_file() {
+        return EntryKind::File;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if ft.is_fifo() {
+            return EntryKind::Fifo;
+        }
+        if ft.is_socket() {
+            return EntryKind::Socket;
+        }
+    }
+    EntryKind::Device
+}
+
+#[cfg(windows)]
+fn windows_attributes(meta: &Metadata) -> Option<u32> {
+    use std::os::windows::fs::MetadataExt;
+    Some(meta.file_attributes())
+}
+
+#[cfg(not(windows))]
+fn windows_attributes(_meta: &Metadata) -> Option<u32> {
+    None
+}
+
+fn has_hidden_attribute(meta: &Metadata) -> bool {
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    windows_attributes(meta).is_some_and(|a| a & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(unix)]
+fn unix_mode(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_meta: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn unix_uid(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.uid())
+}
+
+#[cfg(not(unix))]
+fn unix_uid(_meta: &Metadata) -> Option<u32> {
+    None
+}
+
+/// st_ctime: when the inode last changed. Windows has no such time; its
+/// C runtime reports the creation time there, as PowerShell does.
+#[cfg(unix)]
+fn change_time(meta: &Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    use std::time::Duration;
+    let seconds = Duration::from_secs(meta.ctime().unsigned_abs());
+    let whole = if meta.ctime() >= 0 {
+        UNIX_EPOCH.checked_add(seconds)
+    } else {
+        UNIX_EPOCH.checked_sub(seconds)
+    };
+    whole?.checked_add(Duration::from_nanos(meta.ctime_nsec() as u64))
+}
+
+#[cfg(not(unix))]
+fn change_time(meta: &Metadata) -> Option<SystemTime> {
+    meta.created().ok()
+}
+
+#[cfg(unix)]
+fn block_count(meta: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always in 512-byte units
+    meta.blocks().div_ceil(2)
+}
+
+#[cfg(not(unix))]
+fn block_count(meta: &Metadata) -> u64 {
+    meta.len().div_ceil(1024)
+}
+
+/// Structured output (JSON, CSV...) written one entry at a time
+pub trait RecordWriter {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()>;
+    fn record(&mut self, out: &mut dyn Write, entry: &Entry) -> io::Result<()>;
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// The record writer for the requested output format, if it's structured
+pub fn record_writer(args: &LsArgs) -> Option<Box<dyn RecordWriter>> {
+    let columns = || args.table_columns.clone().unwrap_or_else(|| Column::DEFAULT.to_vec());
+    match args.output {
+        OutputFormat::Text => None,
+        OutputFormat::Json => Some(Box::new(JsonWriter::new(false))),
+        OutputFormat::Ndjson => Some(Box::new(JsonWriter::new(true))),
+        OutputFormat::Csv => Some(Box::new(CsvWriter::new(columns(), false))),
+        OutputFormat::Tsv => Some(Box::new(CsvWriter::new(columns(), true))),
+    }
+}
+
+/// Whether links named on the command line are followed: with -L or -H,
+/// and otherwise unless the listing would show the link itself
+pub fn follows_operand_links(args: &LsArgs) -> bool {
+    args.dereference || args.dereference_command_line || !(args.long_format || args.directory || args.classify)
+}
+
+/// List `args.paths` with the built-in walker. Returns the exit status.
+pub fn run(args: &LsArgs) -> io::Result<i32> {
+    let stdout = io::stdout();
+    let tty = stdout.is_terminal();
+    let mut out = stdout.lock();
+    let mut lister = Lister::new(args, tty);
+    lister.list(&mut out)?;
+    out.flush()?;
+    Ok(lister.status)
+}
+
+struct Lister<'a> {
+    args: &'a LsArgs,
+    tty: bool,
+    quoting: Quoting,
+    eol: &'static str,
+    link_host: Option<String>, // set when names are hyperlinked
+    records: Option<Box<dyn RecordWriter>>, // set for structured output
+    status: i32,
+    ancestors: Vec<PathBuf>, // directories being listed, for -R loops
+    printed_any: bool,
+    git: Option<GitCache>, // set for --git
+}
+
+impl<'a> Lister<'a> {
+    fn new(args: &'a LsArgs, tty: bool) -> Self {
+        Lister {
+            args,
+            tty,
+            quoting: Quoting::resolve(args, tty),
+            eol: if args.zero { "\0" } else { "\n" },
+            link_host: hyperlink::enabled(args, tty).then(hyperlink::hostname),
+            records: record_writer(args),
+            status: 0,
+            ancestors: Vec::new(),
+            printed_any: false,
+            git: args.git.then(GitCache::default),
+        }
+    }
+
+    /// Report an error on stderr. Status 1 is a minor problem, 2 is serious.
+    fn error(&mut self, out: &mut dyn Write, message: String, status: i32) {
+        out.flush().ok();
+        eprintln!("ls: {}", message);
+        self.status = self.status.max(status);
+    }
+
+    fn list(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let args = self.args;
+
+        let follow = follows_operand_links(args);
+
+        if let Some(records) = &mut self.records {
+            records.begin(out)?;
+        }
+
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for operand in &args.paths {
+            let operand_name = operand.to_string_lossy();
+            match Entry::from_path(&operand_path(operand), &operand_name, follow) {
+                Ok(entry) if entry.kind == EntryKind::Dir && !args.directory => dirs.push(entry),
+                Ok(entry) => files.push(entry),
+                Err(e) => {
+                    let message = format!("cannot access '{}': {}", operand_name, describe_error(&e));
+                    self.error(out, message, 2);
+                }
+            }
+        }
+
+        sort_entries(&mut files, args);
+        sort_entries(&mut dirs, args);
+        self.annotate(&mut files);
+
+        if !files.is_empty() {
+            self.write_entries(out, &files, false)?;
+            self.printed_any = true;
+        }
+
+        let show_headers = args.recursive || files.len() + dirs.len() > 1;
+        for dir in &dirs {
+            self.list_dir(out, &dir.path, &dir.name, show_headers, true)?;
+        }
+
+        if let Some(records) = &mut self.records {
+            records.end(out)?;
+        }
+        Ok(())
+    }
+
+    fn list_dir(
+        &mut self,
+        out: &mut dyn Write,
+        path: &Path,
+        display: &str,
+        header: bool,
+        top_level: bool,
+    ) -> io::Result<()> {
+        let args = self.args;
+
+        // With -L a link can lead back to a directory we're already inside
+        let key = args
+            .recursive
+            .then(|| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+        if key.as_ref().is_some_and(|key| self.ancestors.contains(key)) {
+            let message = format!("{}: not listing already-listed directory", display);
+            self.error(out, message, 2);
+            return Ok(());
+        }
+
+        let read = match fs::read_dir(path) {
+            Ok(read) => read,
+            Err(e) => {
+                let message =
+                    format!("cannot open directory '{}': {}", display, describe_error(&e));
+                self.error(out, message, if top_level { 2 } else { 1 });
+                return Ok(());
+            }
+        };
+
+        let rules = args.gitignore.then(|| Gitignore::for_dir(path));
+        let mut entries = Vec::new();
+        if args.all {
+            for dot in [".", ".."] {
+                if let Ok(entry) = Entry::from_path(&path.join(dot), dot, false) {
+                    entries.push(entry);
+                }
+            }
+        }
+        for item in read {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    let message = format!("reading directory '{}': {}", display, describe_error(&e));
+                    self.error(out, message, 1);
+                    continue;
+                }
+            };
+            let name = item.file_name().to_string_lossy().into_owned();
+            if is_ignored(args, &name) || is_git_ignored(rules.as_ref(), &item) {
+                continue;
+            }
+            match Entry::from_path(&item.path(), &name, args.dereference) {
+                Ok(entry) => {
+                    if entry.hidden && !(args.all || args.almost_all) {
+                        continue;
+                    }
+                    entries.push(entry);
+                }
+                Err(e) => {
+                    let shown = path.join(&name);
+                    let message = format!(
+                        "cannot access '{}': {}",
+                        shown.display(),
+                        describe_error(&e)
+                    );
+                    self.error(out, message, 1);
+                }
+            }
+        }
+        sort_entries(&mut entries, args);
+        self.annotate(&mut entries);
+
+        if header && self.records.is_none() {
+            if self.printed_any {
+                write!(out, "{}", self.eol)?;
+            }
+            write!(out, "{}:{}", self.quoting.quote(display), self.eol)?;
+        }
+        self.write_entries(out, &entries, true)?;
+        self.printed_any = true;
+
+        if let Some(key) = key {
+            self.ancestors.push(key);
+            for entry in &entries {
+                if entry.kind == EntryKind::Dir && entry.name != "." && entry.name != ".." {
+                    let child = Path::new(display).join(&entry.name);
+                    let child = child.to_string_lossy().into_owned();
+                    self.list_dir(out, &entry.path, &child, true, false)?;
+                }
+            }
+            self.ancestors.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Fill in --git statuses
+    fn annotate(&mut self, entries: &mut [Entry]) {
+        if let Some(git) = &mut self.git {
+            for entry in entries.iter_mut().filter(|e| e.name != "." && e.name != "..") {
+                entry.git_status = git.status(&entry.path, entry.kind == EntryKind::Dir);
+            }
+        }
+    }
+
+    fn write_entries(&mut self, out: &mut dyn Write, entries: &[Entry], is_dir: bool) -> io::Result<()> {
+        let args = self.args;
+
+        if let Some(records) = &mut self.records {
+            for entry in entries {
+                records.record(out, entry)?;
+            }
+            return Ok(());
+        }
+
+        if args.long_format {
+            if is_dir {
+                let total: u64 = entries.iter().map(|e| e.blocks).sum();
+                write!(out, "total {}{}", self.format_blocks(total), self.eol)?;
+            }
+            return self.write_long(out, entries);
+        }
+
+        let names = self.display_names(entries);
+        let cells: Vec<String> = entries
+            .iter()
+            .zip(names)
+            .map(|(entry, name)| {
+                let mut cell = String::new();
+                if args.show_size {
+                    cell.push_str(&self.format_blocks(entry.blocks));
+                    cell.push(' ');
+                }
+                cell.push_str(&name);
+                if args.classify {
+                    cell.push_str(indicator(entry.kind, entry.mode));
+                }
+                cell
+            })
+            .collect();
+
+        if args.commas {
+            let mut line = CommaLine::new(terminal_width());
+            for cell in &cells {
+                line.name(out, cell)?;
+            }
+            line.end(out)
+        } else if args.one_per_line || !(self.tty || args.columns || args.across) {
+            for cell in &cells {
+                write!(out, "{}{}", cell, self.eol)?;
+            }
+            Ok(())
+        } else {
+            write_columns(out, &cells, terminal_width(), args.across)
+        }
+    }
+
+    /// The long listing: one row per entry, in the --long-columns layout
+    fn write_long(&self, out: &mut dyn Write, entries: &[Entry]) -> io::Result<()> {
+        let args = self.args;
+        let mut columns = args
+            .long_columns
+            .clone()
+            .unwrap_or_else(|| Column::LONG_DEFAULT.to_vec());
+        if args.show_size && !columns.contains(&Column::Blocks) {
+            columns.insert(0, Column::Blocks);
+        }
+        if args.git && !columns.contains(&Column::Git) {
+            let at = columns.iter().position(|&c| c == Column::Name).unwrap_or(0);
+            columns.insert(at, Column::Git);
+        }
+
+        let names = self.display_names(entries);
+        let now = SystemTime::now();
+        let rows: Vec<Vec<String>> = entries
+            .iter()
+            .zip(&names)
+            .map(|(entry, name)| {
+                columns
+                    .iter()
+                    .map(|&column| self.long_cell(column, entry, name, &columns, now))
+                    .collect()
+            })
+            .collect();
+
+        let widths: Vec<usize> = (0..columns.len())
+            .map(|i| rows.iter().map(|r| display_width(&r[i])).max().unwrap_or(0))
+            .collect();
+
+        for row in &rows {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                let last = i + 1 == columns.len();
+                // "name -> target" stays together, as in GNU's layout
+                let glued = columns[i] == Column::Name && columns.get(i + 1) == Some(&Column::Target);
+                let pad = widths[i] - display_width(cell);
+
+                if i > 0 {
+                    line.push(' ');
+                }
+                if columns[i].is_numeric() {
+                    line.extend(std::iter::repeat_n(' ', pad));
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    if !last && !glued {
+                        line.extend(std::iter::repeat_n(' ', pad));
+                    }
+                }
+            }
+            write!(out, "{}{}", line.trim_end(), self.eol)?;
+        }
+        Ok(())
+    }
+
+    /// One cell of the long listing. `name` is already quoted and linked.
+    fn long_cell(
+        &self,
+        column: Column,
+        entry: &Entry,
+        name: &str,
+        columns: &[Column],
+        now: SystemTime,
+    ) -> String {
+        let classify = self.args.classify;
+        match column {
+            Column::Perms => permissions_string(entry),
+            Column::Attrs => attribute_string(entry),
+            Column::Size => self.format_size(entry.size),
+            Column::Blocks => self.format_blocks(entry.blocks),
+            Column::Mtime => format_time(entry.modified, now),
+            Column::Ctime => format_time(entry.changed, now),
+            Column::Atime => format_time(entry.accessed, now),
+            Column::Name => {
+                // A link's indicator goes on its target when that's shown
+                let on_target = entry.link_target.is_some() && columns.contains(&Column::Target);
+                if classify && !on_target {
+                    format!("{}{}", name, indicator(entry.kind, entry.mode))
+                } else {
+                    name.to_string()
+                }
+            }
+            Column::Target => match &entry.link_target {
+                Some(target) => {
+                    let mut cell = format!("-> {}", self.quoting.quote(&target.to_string_lossy()));
+                    if let (true, Some(kind)) = (classify, entry.target_kind) {
+                        cell.push_str(indicator(kind, None));
+                    }
+                    cell
+                }
+                None => String::new(),
+            },
+            _ => column.value(entry),
+        }
+    }
+
+    /// Quote names for display. In aligned layouts, when some names gained
+    /// an opening quote, the rest are indented to keep the columns straight.
+    fn display_names(&self, entries: &[Entry]) -> Vec<String> {
+        let mut names: Vec<String> = entries.iter().map(|e| self.quoting.quote(&e.name)).collect();
+
+        let aligned = self.args.long_format
+            || ((self.tty || self.args.columns || self.args.across)
+                && !(self.args.one_per_line || self.args.commas));
+        let quoted = |name: &String| name.starts_with(['\'', '"', '$']);
+        if aligned && self.quoting.aligns_outer_quotes() && names.iter().any(quoted) {
+            for name in names.iter_mut().filter(|n| !quoted(n)) {
+                name.insert(0, ' ');
+            }
+        }
+
+        if let Some(host) = &self.link_host {
+            for (name, entry) in names.iter_mut().zip(entries) {
+                let indent = if name.starts_with(' ') { " " } else { "" };
+                let linked = hyperlink::wrap(&name[indent.len()..], &entry.path, host);
+                *name = format!("{}{}", indent, linked);
+            }
+        }
+        names
+    }
+
+    fn format_size(&self, size: u64) -> String {
+        if self.args.human_readable {
+            human_size(size)
+        } else {
+            size.to_string()
+        }
+    }
+
+    fn format_blocks(&self, blocks: u64) -> String {
+        if self.args.human_readable {
+            human_size(blocks * 1024)
+        } else {
+            blocks.to_string()
+        }
+    }
+}
+
+/// Whether -I/--ignore, or --hide without -a/-A, leaves a name out.
+/// --gitignore always leaves out .git itself.
+pub fn is_ignored(args: &LsArgs, name: &str) -> bool {
+    let matches = |patterns: &[String]| patterns.iter().any(|p| glob::matches(p, name));
+    matches(&args.ignore)
+        || (!(args.all || args.almost_all) && matches(&args.hide))
+        || (args.gitignore && name == ".git")
+}
+
+/// Whether --gitignore's rules for the directory leave an item out
+pub fn is_git_ignored(rules: Option<&Gitignore>, item: &fs::DirEntry) -> bool {
+    // git treats a link to a directory as a file
+    let is_dir = item.file_type().is_ok_and(|t| t.is_dir());
+    rules.is_some_and(|rules| rules.is_ignored(&item.path(), is_dir))
+}
+
+/// Sort in place: by name, or by time/size (largest/newest first), with -r
+/// reversing and -U keeping directory order
+pub fn sort_entries(entries: &mut [Entry], args: &LsArgs) {
+    if args.no_sort {
+        return;
+    }
+
+    let by_name = |a: &Entry, b: &Entry| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.name.cmp(&b.name))
+    };
+
+    if args.sort_by_time {
+        entries.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| by_name(a, b)));
+    } else if args.sort_by_size {
+        entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| by_name(a, b)));
+    } else {
+        entries.sort_by(by_name);
+    }
+
+    if args.reverse {
+        entries.reverse();
+    }
+}
+
+/// -F suffix for an entry kind
+pub fn indicator(kind: EntryKind, mode: Option<u32>) -> &'static str {
+    match kind {
+        EntryKind::Dir => "/",
+        EntryKind::Symlink => "@",
+        EntryKind::Fifo => "|",
+        EntryKind::Socket => "=",
+        EntryKind::File if mode.is_some_and(|m| m & 0o111 != 0) => "*",
+        _ => "",
+    }
+}
+
+/// PowerShell-style Mode string: directory/link, archive, read-only,
+/// hidden, system, reparse point
+fn attribute_string(entry: &Entry) -> String {
+    let names = crate::json::attribute_names(entry);
+    let has = |name: &str| names.contains(&name);
+    let first = match entry.kind {
+        EntryKind::Dir => 'd',
+        EntryKind::Symlink => 'l',
+        _ => '-',
+    };
+
+    [
+        (first, first != '-'),
+        ('a', has("archive")),
+        ('r', has("readonly")),
+        ('h', has("hidden")),
+        ('s', has("system")),
+        ('l', has("reparsepoint")),
+    ]
+    .iter()
+    .map(|&(c, set)| if set { c } else { '-' })
+    .collect()
+}
+
+/// The 8.3 short name of a file, where the file system keeps one
+#[cfg(windows)]
+pub fn short_name(path: &Path) -> Option<String> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetShortPathNameW(long_path: *const u16, short_path: *mut u16, length: u32) -> u32;
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut buffer = vec![0u16; 1024];
+    // SAFETY: `wide` is NUL-terminated and `buffer` holds `buffer.len()` u16s
+    let len = unsafe { GetShortPathNameW(wide.as_ptr(), buffer.as_mut_ptr(), buffer.len() as u32) };
+    if len == 0 || len as usize > buffer.len() {
+        return None;
+    }
+    let short = PathBuf::from(OsString::from_wide(&buffer[..len as usize]));
+    short.file_name().map(|n| n.to_string_lossy().into_owned())
+}
+
+#[cfg(not(windows))]
+pub fn short_name(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Unix-style permission string. Windows has no mode bits, so they're
+/// approximated from the read-only attribute and the file extension.
+pub fn permissions_string(entry: &Entry) -> String {
+    let type_char = match entry.kind {
+        EntryKind::File => '-',
+        EntryKind::Dir => 'd',
+        EntryKind::Symlink => 'l',
+        EntryKind::Fifo => 'p',
+        EntryKind::Socket => 's',
+        EntryKind::Device => 'c',
+    };
+
+    let mode = entry.mode.unwrap_or_else(|| {
+        let executable = entry.kind == EntryKind::Dir
+            || Path::new(&entry.name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| {
+                    ["exe", "com", "bat", "cmd", "ps1"].contains(&e.to_ascii_lowercase().as_str())
+                });
+        let mut mode = if entry.readonly { 0o444 } else { 0o666 };
+        if executable {
+            mode |= 0o111;
+        }
+        mode
+    });
+
+    let mut s = String::with_capacity(10);
+    s.push(type_char);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+/// GNU-style -h size: 1023, 1.0K, 9.9K, 10K, 1.5M ...
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    // Round up, like GNU, so a size is never under-reported
+    let tenths = (size * 10.0).ceil() / 10.0;
+    if tenths < 10.0 {
+        format!("{:.1}{}", tenths, UNITS[unit])
+    } else {
+        format!("{:.0}{}", size.ceil(), UNITS[unit])
+    }
+}
+
+/// Format a timestamp the way ls -l does: "Oct 18 13:48" for recent files,
+/// "Oct 18  2025" for anything older than six months or in the future.
+/// Times are shown in UTC.
+pub fn format_time(time: Option<SystemTime>, now: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    const SIX_MONTHS: i64 = 31_556_952 / 2;
+
+    let Some(time) = time else {
+        return "           ?".to_string();
+    };
+
+    let secs = unix_seconds(time);
+    let (_, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let month = MONTHS[(month - 1) as usize];
+    let age = unix_seconds(now) - secs;
+
+    if (0..SIX_MONTHS).contains(&age) {
+        let of_day = secs.rem_euclid(86_400);
+        format!("{} {:>2} {:02}:{:02}", month, day, of_day / 3600, of_day % 3600 / 60)
+    } else {
+        let (year, _, _) = civil_from_days(secs.div_euclid(86_400));
+        format!("{} {:>2} {:>5}", month, day, year)
+    }
+}
+
+pub fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Days since 1970-01-01 to (year, month, day), after Howard Hinnant's
+/// `civil_from_days`
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&w| w > 0)
+        .unwrap_or(80)
+}
+
+/// Lay out cells in columns, filled top to bottom as ls -C does, or left
+/// to right as -x does
+fn write_columns(out: &mut dyn Write, cells: &[String], width: usize, across: bool) -> io::Result<()> {
+    if cells.is_empty() {
+        return Ok(());
+    }
+
+    let lens: Vec<usize> = cells.iter().map(|c| display_width(c)).collect();
+    let at = |rows: usize, cols: usize, row: usize, col: usize| {
+        if across {
+            row * cols + col
+        } else {
+            col * rows + row
+        }
+    };
+    let mut layout = (cells.len(), vec![lens.iter().copied().max().unwrap_or(0)]);
+
+    for cols in (2..=cells.len()).rev() {
+        let rows = cells.len().div_ceil(cols);
+        let widths: Vec<usize> = (0..cols)
+            .filter_map(|col| (0..rows).filter_map(|row| lens.get(at(rows, cols, row, col))).max())
+            .copied()
+            .collect();
+        let total: usize = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+        if total <= width {
+            layout = (rows, widths);
+            break;
+        }
+    }
+
+    let (rows, widths) = layout;
+    let cols = widths.len();
+    for row in 0..rows {
+        let mut line = String::new();
+        for (col, col_width) in widths.iter().enumerate() {
+            let Some(cell) = cells.get(at(rows, cols, row, col)) else {
+                break;
+            };
+            line.push_str(cell);
+            if col + 1 < cols && cells.get(at(rows, cols, row, col + 1)).is_some() {
+                let pad = col_width - lens[at(rows, cols, row, col)] + 2;
+                line.extend(std::iter::repeat_n(' ', pad));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Names separated by commas and wrapped at the line width, as ls -m
+/// prints them. Each name is written as it comes.
+pub struct CommaLine {
+    width: usize,
+    pos: Option<usize>, // column the line has reached, once a name is out
+}
+
+impl CommaLine {
+    pub fn new(width: usize) -> Self {
+        CommaLine { width, pos: None }
+    }
+
+    pub fn name(&mut self, out: &mut dyn Write, name: &str) -> io::Result<()> {
+        let len = display_width(name);
+        let pos = match self.pos {
+            None => 0,
+            Some(pos) if pos + len + 2 < self.width => {
+                write!(out, ", ")?;
+                pos + 2
+            }
+            Some(_) => {
+                writeln!(out, ",")?;
+                0
+            }
+        };
+        write!(out, "{}", name)?;
+        self.pos = Some(pos + len);
+        Ok(())
+    }
+
+    pub fn end(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        match self.pos.take() {
+            Some(_) => writeln!(out),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Width of a cell on screen, not counting OSC 8 hyperlink sequences
+fn display_width(cell: &str) -> usize {
+    let mut width = 0;
+    let mut rest = cell;
+    while let Some(start) = rest.find("\x1b]8;") {
+        width += rest[..start].chars().count();
+        rest = match rest[start..].find("\x1b\\") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    width + rest.chars().count()
+}
+
+/// Describe an I/O error the way GNU tools do, without Rust's "(os error N)"
+pub fn describe_error(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::NotFound => "No such file or directory".to_string(),
+        io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        _ => {
+            let text = e.to_string();
+            match text.find(" (os error") {
+                Some(pos) => text[..pos].to_string(),
+                None => text,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{ColorOption, QuotingStyle};
+    use crate::testdir::TempDir;
+
+    fn list_to_string(args: &LsArgs) -> (String, i32) {
+        let mut out = Vec::new();
+        let mut lister = Lister::new(args, false);
+        lister.list(&mut out).unwrap();
+        (String::from_utf8(out).unwrap(), lister.status)
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1024), "1.0K");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(20 * 1024), "20K");
+        assert_eq!(human_size(5 * 1024 * 1024), "5.0M");
+    }
+
+    #[test]
+    fn test_format_time() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_760_795_284); // 2025-10-18 13:48:04
+        let recent = now - std::time::Duration::from_secs(3600);
+        assert_eq!(format_time(Some(recent), now), "Oct 18 12:48");
+        let old = UNIX_EPOCH + std::time::Duration::from_secs(946_684_800); // 2000-01-01
+        assert_eq!(format_time(Some(old), now), "Jan  1  2000");
+    }
+
+    #[test]
+    fn test_missing_operand() {
+        let args = LsArgs {
+            paths: vec![PathBuf::from("/definitely/not/here")],
+            ..Default::default()
+        };
+        let (out, status) = list_to_string(&args);
+        assert!(out.is_empty());
+        assert_eq!(status, 2);
+    }
+
+    #[test]
+    fn test_lists_and_hides_dotfiles() {
+        let dir = TempDir::new("hidden");
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join(".secret"), "").unwrap();
+
+        let mut args = LsArgs {
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let (out, status) = list_to_string(&args);
+        assert_eq!(out, "a.txt\nb.txt\n");
+        assert_eq!(status, 0);
+
+        args.almost_all = true;
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, ".secret\na.txt\nb.txt\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_unicode_names() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let dir = TempDir::new("bytes");
+        let inner = dir.join(OsString::from_vec(b"d\xe9j\xe0".to_vec()));
+        if fs::create_dir(&inner).is_err() {
+            return; // the file system insists on UTF-8
+        }
+        fs::write(inner.join(OsString::from_vec(b"\xff.txt".to_vec())), "").unwrap();
+
+        // Found by exact bytes, shown with replacement characters
+        let args = LsArgs {
+            paths: vec![inner.clone()],
+            ..Default::default()
+        };
+        let (out, status) = list_to_string(&args);
+        assert_eq!(out, "\u{FFFD}.txt\n");
+        assert_eq!(status, 0);
+
+        let args = LsArgs {
+            paths: vec![dir.to_path_buf()],
+            recursive: true,
+            ..Default::default()
+        };
+        let (out, status) = list_to_string(&args);
+        assert!(out.contains("d\u{FFFD}j\u{FFFD}:\n\u{FFFD}.txt\n"), "{}", out);
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn test_ignore_and_hide_patterns() {
+        let dir = TempDir::new("ignore");
+        for name in ["a.rs", "b.o", "c.tmp"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let mut args = LsArgs {
+            paths: vec![dir.to_path_buf()],
+            ignore: vec!["*.o".to_string()],
+            hide: vec!["*.tmp".to_string()],
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, "a.rs\n");
+
+        // -A shows what --hide hides, but not what -I ignores
+        args.almost_all = true;
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, "a.rs\nc.tmp\n");
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let dir = TempDir::new("gitignore");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join("src/run.log"), "").unwrap();
+
+        let args = LsArgs {
+            paths: vec![dir.to_path_buf()],
+            all: true,
+            recursive: true,
+            gitignore: true,
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert!(out.contains(".gitignore") && out.contains("main.rs"));
+        assert!(!out.contains("target") && !out.contains("run.log"));
+        assert!(!out.contains(".git\n"));
+    }
+
+    #[test]
+    fn test_display_width_skips_hyperlinks() {
+        let linked = hyperlink::wrap("name", Path::new("/tmp/name"), "host");
+        assert_eq!(display_width(&linked), 4);
+        assert_eq!(display_width(&format!(" {}  x", linked)), 8);
+    }
+
+    #[test]
+    fn test_column_layouts() {
+        let cells: Vec<String> = ["a", "bb", "c", "dd", "e"].map(String::from).to_vec();
+        let layout = |across| {
+            let mut out = Vec::new();
+            write_columns(&mut out, &cells, 12, across).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(layout(false), "a   c   e\nbb  dd\n");
+        assert_eq!(layout(true), "a  bb  c  dd\ne\n");
+
+        let mut out = Vec::new();
+        let mut line = CommaLine::new(10);
+        for cell in &cells {
+            line.name(&mut out, cell).unwrap();
+        }
+        line.end(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a, bb, c,\ndd, e\n");
+    }
+
+    #[test]
+    fn test_hyperlinks_wrap_names() {
+        let dir = TempDir::new("hyperlink");
+        fs::write(dir.join("a b"), "").unwrap();
+
+        let args = LsArgs {
+            hyperlink: Some(ColorOption::Always),
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert!(out.starts_with("\x1b]8;;file://"));
+        assert!(out.contains("/a%20b\x1b\\a b\x1b]8;;\x1b\\\n"));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let dir = TempDir::new("json");
+        fs::write(dir.join("a.txt"), "abc").unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("b.txt"), "").unwrap();
+
+        let mut args = LsArgs {
+            recursive: true,
+            output: OutputFormat::Json,
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert!(out.starts_with("[\n{\"version\":1,\"name\":\"a.txt\""));
+        assert!(out.contains("\"type\":\"file\",\"size\":3,"));
+        assert!(out.contains("\"name\":\"b.txt\""));
+        assert!(out.ends_with("}\n]\n"));
+        assert!(!out.contains("sub:"));
+
+        args.output = OutputFormat::Ndjson;
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.lines().all(|l| l.starts_with('{') && l.ends_with('}')));
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let dir = TempDir::new("csv");
+        fs::write(dir.join("a,b.txt"), "abc").unwrap();
+
+        let args = LsArgs::parse([
+            "ls",
+            "--format=csv",
+            "--columns=name,size,ext",
+            &dir.to_string_lossy(),
+        ])
+        .unwrap();
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, "name,size,ext\r\n\"a,b.txt\",3,.txt\r\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ctime_is_inode_change() {
+        let dir = TempDir::new("ctime");
+        let path = dir.join("a");
+        let file = fs::File::create(&path).unwrap();
+        let past = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        file.set_modified(past).unwrap();
+
+        // Setting the time changed the inode, so ctime is now, not mtime
+        let entry = Entry::from_path(&path, "a", false).unwrap();
+        assert_eq!(entry.modified, Some(past));
+        assert!(entry.changed.unwrap() > past);
+    }
+
+    #[test]
+    fn test_long_columns() {
+        let dir = TempDir::new("long-columns");
+        fs::write(dir.join("big.txt"), "0123456789").unwrap();
+        fs::write(dir.join("x"), "").unwrap();
+
+        let args = LsArgs::parse([
+            "ls",
+            "--long-columns=size,name,ext",
+            &dir.to_string_lossy(),
+        ])
+        .unwrap();
+        let (out, _) = list_to_string(&args);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "10 big.txt .txt");
+        assert_eq!(lines[2], " 0 x");
+    }
+
+    #[test]
+    fn test_zero_terminates_entries() {
+        let dir = TempDir::new("zero");
+        fs::write(dir.join("with\nnewline"), "").unwrap();
+        fs::write(dir.join("plain"), "").unwrap();
+
+        let args = LsArgs::parse(["ls", "--zero", &dir.to_string_lossy()]).unwrap();
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, "plain\0with\nnewline\0");
+    }
+
+    #[test]
+    fn test_quoting_style_applies_to_names() {
+        let dir = TempDir::new("quoting");
+        fs::write(dir.join("my file"), "").unwrap();
+        fs::write(dir.join("plain"), "").unwrap();
+
+        let mut args = LsArgs {
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, "my file\nplain\n");
+
+        args.quoting_style = Some(QuotingStyle::ShellEscape);
+        let (out, _) = list_to_string(&args);
+        assert_eq!(out, "'my file'\nplain\n");
+
+        // Long listings indent unquoted names to line up with quoted ones
+        args.long_format = true;
+        let (out, _) = list_to_string(&args);
+        assert!(out.lines().any(|l| l.ends_with(" 'my file'")));
+        assert!(out.lines().any(|l| l.ends_with("  plain")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_long_format_shows_link_target() {
+        let dir = TempDir::new("links");
+        fs::write(dir.join("real.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink("real.txt", dir.join("link.txt")).unwrap();
+
+        let mut args = LsArgs {
+            long_format: true,
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert!(out.lines().any(|l| l.starts_with('l') && l.ends_with("link.txt -> real.txt")));
+
+        // -L shows the target's details instead of the link
+        args.dereference = true;
+        let (out, _) = list_to_string(&args);
+        assert!(!out.contains("->"));
+        assert!(out.lines().any(|l| l.starts_with('-') && l.ends_with("link.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_line_link_with_h() {
+        let dir = TempDir::new("cmdline");
+        fs::create_dir(dir.join("real")).unwrap();
+        fs::write(dir.join("real").join("inside.txt"), "").unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("real", &link).unwrap();
+
+        let mut args = LsArgs {
+            long_format: true,
+            paths: vec![link.clone()],
+            ..Default::default()
+        };
+        let (out, _) = list_to_string(&args);
+        assert!(out.contains("-> real"));
+
+        args.dereference_command_line = true;
+        let (out, _) = list_to_string(&args);
+        assert!(out.contains("inside.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_recursive_link_loop() {
+        let dir = TempDir::new("loop");
+        fs::create_dir(dir.join("sub")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("sub").join("up")).unwrap();
+
+        let args = LsArgs {
+            recursive: true,
+            dereference: true,
+            paths: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        let (out, status) = list_to_string(&args);
+        assert!(out.contains("sub:"));
+        assert_eq!(status, 2);
+
+        // Only ancestors count: a link to a sibling, or the same operand
+        // twice, is listed again
+        fs::remove_file(dir.join("sub").join("up")).unwrap();
+        fs::create_dir(dir.join("other")).unwrap();
+        std::os::unix::fs::symlink("../other", dir.join("sub").join("side")).unwrap();
+        let (out, status) = list_to_string(&args);
+        assert!(out.contains("sub/side:"));
+        assert_eq!(status, 0);
+
+        let args = LsArgs {
+            paths: vec![dir.to_path_buf(), dir.to_path_buf()],
+            ..args
+        };
+        let (out, status) = list_to_string(&args);
+        assert_eq!(out.matches("other:").count(), 2);
+        assert_eq!(status, 0);
+    }
+}
@@ -0,0 +1,432 @@
+//! Operand path normalization
+//!
+//! Paths arrive in whatever form the user's shell or clipboard produced:
+//! Git Bash's `/c/Users`, Cygwin's `/cygdrive/c`, WSL's `/mnt/c`, `~` and
+//! `$HOME`, or a `file://` URI copied from a browser. Windows tools only
+//! understand `C:\Users`, so operands are rewritten before they're handed
+//! on.
+//!
+//! Windows' own forms are kept: UNC shares (`\\server\share`), device
+//! paths (`\\.\COM1`), verbatim paths (`\\?\C:\...`, passed through
+//! untouched) and drive-relative ones (`C:foo`). Paths too long for
+//! MAX_PATH get the `\\?\` prefix that lifts the limit.
+
+use std::path::{Path, PathBuf};
+
+/// Paths this long need the `\\?\` prefix. MAX_PATH is 260, but a
+/// directory must leave room for an 8.3 name below it, as CreateDirectory
+/// does.
+const LONG_PATH: usize = 248;
+
+/// Convert Unix-style paths to Windows-style, reading variables, the home
+/// directory and the current directory from the environment
+pub fn to_windows_path(path: &str) -> String {
+    let env = |name: &str| std::env::var(name).ok();
+    let path = normalize(path, &env);
+    match std::env::current_dir() {
+        Ok(cwd) => extend_long_path(&path, &cwd.to_string_lossy(), &env),
+        Err(_) => path,
+    }
+}
+
+/// How Windows reads a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathForm {
+    Relative,      // src
+    Rooted,        // \src, on the current drive
+    DriveRelative, // C:src, below the current directory of drive C
+    Absolute,      // C:\src
+    Unc,           // \\server\share
+    Device,        // \\.\COM1
+    Verbatim,      // \\?\C:\src, taken as is
+}
+
+impl PathForm {
+    pub fn of(path: &str) -> PathForm {
+        let bytes = path.as_bytes();
+        if path.starts_with(r"\\?\") {
+            PathForm::Verbatim
+        } else if path.starts_with(r"\\.\") {
+            PathForm::Device
+        } else if path.starts_with(r"\\") {
+            PathForm::Unc
+        } else if path.starts_with('\\') {
+            PathForm::Rooted
+        } else if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            if bytes.get(2) == Some(&b'\\') {
+                PathForm::Absolute
+            } else {
+                PathForm::DriveRelative
+            }
+        } else {
+            PathForm::Relative
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            PathForm::Relative => "relative to the current directory",
+            PathForm::Rooted => "from the root of the current drive",
+            PathForm::DriveRelative => "relative to the current directory of its drive",
+            PathForm::Absolute => "absolute",
+            PathForm::Unc => "UNC share",
+            PathForm::Device => "device namespace",
+            PathForm::Verbatim => "extended-length, not parsed by Windows",
+        }
+    }
+}
+
+/// The path to open for an operand
+pub fn operand_path(operand: &Path) -> PathBuf {
+    match operand.to_str() {
+        Some(operand) if cfg!(windows) => PathBuf::from(to_windows_path(operand)),
+        _ => operand.to_path_buf(),
+    }
+}
+
+/// Rewrite `path` into a Windows path. `env` looks up environment
+/// variables, so the result depends on nothing else.
+pub fn normalize(path: &str, env: &dyn Fn(&str) -> Option<String>) -> String {
+    // Verbatim paths reach the file system unparsed, so even their
+    // separators must stay as written
+    if path.starts_with(r"\\?\") {
+        return path.to_string();
+    }
+    if path.starts_with("//?/") || path.starts_with("//./") || path.starts_with(r"\\.\") {
+        return path.replace('/', "\\");
+    }
+
+    let path = match from_file_uri(path) {
+        Some(path) => path,
+        None => expand_variables(&expand_tilde(path, env), env),
+    };
+    let path = from_unix_drive(&path).unwrap_or(path);
+    path.replace('/', "\\")
+}
+
+/// Give a path that's too long for MAX_PATH the `\\?\` prefix. That turns
+/// off Windows' own parsing, so the path is first made absolute, against
+/// `cwd` or, for `C:foo`, the drive's current directory in `%=C:%`, and
+/// its `.` and `..` resolved.
+pub fn extend_long_path(path: &str, cwd: &str, env: &dyn Fn(&str) -> Option<String>) -> String {
+    if path.encode_utf16().count() < LONG_PATH {
+        return path.to_string();
+    }
+    let cwd = cwd.replace('/', "\\");
+    let (prefix, root, rest) = match PathForm::of(path) {
+        PathForm::Verbatim | PathForm::Device => return path.to_string(),
+        PathForm::Unc => {
+            // \\server\share stays the root that .. can't climb above
+            let mut parts = path[2..].splitn(3, '\\');
+            let server = parts.next().unwrap_or("");
+            let share = parts.next().unwrap_or("");
+            (r"\\?\UNC\", format!("{}\\{}", server, share), parts.next().unwrap_or("").to_string())
+        }
+        PathForm::Absolute => (r"\\?\", path[..2].to_string(), path[3..].to_string()),
+        PathForm::DriveRelative => {
+            let drive = path[..2].to_ascii_uppercase();
+            let current = env(&format!("={}", drive))
+                .or_else(|| cwd.get(..2).filter(|d| d.eq_ignore_ascii_case(&drive)).map(|_| cwd.clone()))
+                .unwrap_or_else(|| format!("{}\\", drive));
+            return extend_long_path(&format!("{}\\{}", current.trim_end_matches('\\'), &path[2..]), &cwd, env);
+        }
+        PathForm::Rooted => match PathForm::of(&cwd) {
+            PathForm::Absolute => (r"\\?\", cwd[..2].to_string(), path[1..].to_string()),
+            _ => return path.to_string(),
+        },
+        PathForm::Relative => {
+            if !matches!(PathForm::of(&cwd), PathForm::Absolute | PathForm::Unc) {
+                return path.to_string();
+            }
+            return extend_long_path(&format!("{}\\{}", cwd.trim_end_matches('\\'), path), &cwd, env);
+        }
+    };
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in rest.split('\\') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("{}{}\\{}", prefix, root, parts.join("\\"))
+}
+
+/// The home directory: %USERPROFILE%, else $HOME
+fn home(env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    env("USERPROFILE").or_else(|| env("HOME")).filter(|h| !h.is_empty())
+}
+
+/// `~` and `~/x` name the home directory, `~user` a sibling of it
+fn expand_tilde(path: &str, env: &dyn Fn(&str) -> Option<String>) -> String {
+    let Some(rest) = path.strip_prefix('~') else {
+        return path.to_string();
+    };
+    let (user, rest) = match rest.find(['/', '\\']) {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let Some(home) = home(env) else {
+        return path.to_string();
+    };
+    if user.is_empty() {
+        return format!("{}{}", home, rest);
+    }
+
+    // Profiles live side by side, as in C:\Users\me and C:\Users\other
+    let home = home.trim_end_matches(['/', '\\']);
+    match home.rfind(['/', '\\']) {
+        Some(i) => format!("{}{}{}", &home[..=i], user, rest),
+        None => path.to_string(),
+    }
+}
+
+/// Replace `$NAME` and `${NAME}`. Unset variables are left as written, so
+/// the error names what was typed.
+fn expand_variables(path: &str, env: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut result = String::new();
+    let mut rest = path;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+        let value = if valid {
+            if name == "HOME" {
+                env("HOME").or_else(|| home(env))
+            } else {
+                env(name)
+            }
+        } else {
+            None
+        };
+        match value {
+            Some(value) => {
+                result.push_str(&value);
+                rest = &after[len..];
+            }
+            None => {
+                result.push('$');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// `/c/x` (MSYS, Git Bash), `/cygdrive/c/x` (Cygwin) and `/mnt/c/x` (WSL)
+/// as `C:/x`
+fn from_unix_drive(path: &str) -> Option<String> {
+    let rest = path
+        .strip_prefix("/cygdrive/")
+        .or_else(|| path.strip_prefix("/mnt/"))
+        .or_else(|| path.strip_prefix('/'))?;
+    let mut chars = rest.chars();
+    let letter = chars.next().filter(char::is_ascii_alphabetic)?;
+    let rest = chars.as_str();
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+    let rest = if rest.is_empty() { "/" } else { rest };
+    Some(format!("{}:{}", letter.to_ascii_uppercase(), rest))
+}
+
+/// `file:///C:/x` as `C:/x` and `file://server/share` as `//server/share`,
+/// with %XX escapes decoded
+fn from_file_uri(path: &str) -> Option<String> {
+    let prefix = path.get(..7)?;
+    if !prefix.eq_ignore_ascii_case("file://") {
+        return None;
+    }
+    let rest = &path[7..];
+    let rest = if rest.get(..10).is_some_and(|p| p.eq_ignore_ascii_case("localhost/")) {
+        &rest[9..]
+    } else {
+        rest
+    };
+
+    let decoded = percent_decode(rest);
+    match decoded.strip_prefix('/') {
+        // file:///C:/x
+        Some(local) if local.get(1..2) == Some(":") || local.get(1..2) == Some("|") => {
+            Some(format!("{}:{}", &local[..1], &local[2..]))
+        }
+        // file:///home/me keeps its root
+        Some(_) => Some(decoded),
+        // file://server/share
+        None => Some(format!("//{}", decoded)),
+    }
+}
+
+/// Decode %XX escapes as UTF-8
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "USERPROFILE" => Some(r"C:\Users\me".to_string()),
+            "PROJ" => Some(r"D:\proj".to_string()),
+            "DATA_1" => Some("/mnt/d/data".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            // Plain paths only change separators
+            ("src", "src"),
+            ("src/main.rs", r"src\main.rs"),
+            (r"C:\Windows", r"C:\Windows"),
+            ("C:/Windows/System32", r"C:\Windows\System32"),
+            ("..", ".."),
+            // MSYS and Git Bash
+            ("/c/Users/me/src", r"C:\Users\me\src"),
+            ("/d", r"D:\"),
+            ("/d/", r"D:\"),
+            ("/usr/bin", r"\usr\bin"),
+            ("/cd/x", r"\cd\x"),
+            // Cygwin
+            ("/cygdrive/d/data", r"D:\data"),
+            ("/cygdrive/e", r"E:\"),
+            // WSL
+            ("/mnt/c/work", r"C:\work"),
+            ("/mnt/wsl/x", r"\mnt\wsl\x"),
+            // Home directories
+            ("~", r"C:\Users\me"),
+            ("~/src", r"C:\Users\me\src"),
+            (r"~\src", r"C:\Users\me\src"),
+            ("~alice", r"C:\Users\alice"),
+            ("~alice/docs", r"C:\Users\alice\docs"),
+            ("a~b", "a~b"),
+            // Variables
+            ("$HOME/src", r"C:\Users\me\src"),
+            ("${HOME}/src", r"C:\Users\me\src"),
+            ("$PROJ/x", r"D:\proj\x"),
+            ("${PROJ}x", r"D:\projx"),
+            ("$DATA_1/in", r"D:\data\in"),
+            ("$UNSET/x", r"$UNSET\x"),
+            ("${UNSET}", "${UNSET}"),
+            ("${PROJ", "${PROJ"),
+            ("cost$5", "cost$5"),
+            ("$", "$"),
+            // file:// URIs
+            ("file:///C:/Users/me/My%20Docs", r"C:\Users\me\My Docs"),
+            ("FILE:///c|/x", r"c:\x"),
+            ("file://localhost/D:/data", r"D:\data"),
+            ("file://server/share/dir", r"\\server\share\dir"),
+            ("file://aaaaaaaaa€", r"\\aaaaaaaaa€"),
+            ("file:///C:/%E2%82%AC%zz", r"C:\€%zz"),
+            ("file:///C:/$HOME", r"C:\$HOME"),
+            // Windows' own forms
+            ("//server/share/x", r"\\server\share\x"),
+            (r"\\server\share", r"\\server\share"),
+            (r"\\?\C:\a/b\..", r"\\?\C:\a/b\.."),
+            ("//?/C:/x", r"\\?\C:\x"),
+            (r"\\.\COM1", r"\\.\COM1"),
+            ("//./PhysicalDrive0", r"\\.\PhysicalDrive0"),
+            ("C:foo/bar", r"C:foo\bar"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(input, &env), expected, "normalizing {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_path_forms() {
+        let cases = [
+            ("src", PathForm::Relative),
+            (r"..\x", PathForm::Relative),
+            (r"\Windows", PathForm::Rooted),
+            ("C:foo", PathForm::DriveRelative),
+            ("C:", PathForm::DriveRelative),
+            (r"C:\", PathForm::Absolute),
+            (r"\\server\share", PathForm::Unc),
+            (r"\\.\COM1", PathForm::Device),
+            (r"\\?\C:\x", PathForm::Verbatim),
+            (r"\\?\UNC\server\share", PathForm::Verbatim),
+        ];
+        for (path, form) in cases {
+            assert_eq!(PathForm::of(path), form, "classifying {:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_long_paths() {
+        let long = "d".repeat(250);
+        let cwd = r"C:\work";
+        let env = |name: &str| (name == "=D:").then(|| r"D:\data".to_string());
+        let extend = |path: &str| extend_long_path(path, cwd, &env);
+
+        // Short paths are left alone
+        assert_eq!(extend(r"C:\short\..\x"), r"C:\short\..\x");
+        assert_eq!(extend("C:foo"), "C:foo");
+
+        let cases = [
+            (format!(r"C:\a\.\b\..\{}", long), format!(r"\\?\C:\a\{}", long)),
+            (format!(r"sub\{}", long), format!(r"\\?\C:\work\sub\{}", long)),
+            (format!(r"..\{}", long), format!(r"\\?\C:\{}", long)),
+            (format!(r"\top\{}", long), format!(r"\\?\C:\top\{}", long)),
+            (format!("D:{}", long), format!(r"\\?\D:\data\{}", long)),
+            (format!("E:{}", long), format!(r"\\?\E:\{}", long)),
+            (format!(r"\\srv\share\..\..\{}", long), format!(r"\\?\UNC\srv\share\{}", long)),
+            (format!(r"\\?\C:\{}\..", long), format!(r"\\?\C:\{}\..", long)),
+            (format!(r"\\.\pipe\{}", long), format!(r"\\.\pipe\{}", long)),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(extend(&path), expected, "extending {:?}", path);
+        }
+
+        // The limit counts UTF-16 units, as Windows does
+        let wide = "€".repeat(200);
+        assert_eq!(extend(&format!(r"C:\{}", wide)), format!(r"C:\{}", wide));
+    }
+
+    #[test]
+    fn test_home_fallbacks() {
+        let unix = |name: &str| (name == "HOME").then(|| "/c/Users/me".to_string());
+        assert_eq!(normalize("~/x", &unix), r"C:\Users\me\x");
+        assert_eq!(normalize("~bob", &unix), r"C:\Users\bob");
+
+        let none = |_: &str| None;
+        assert_eq!(normalize("~/x", &none), r"~\x");
+        assert_eq!(normalize("$HOME", &none), "$HOME");
+    }
+}
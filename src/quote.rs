@@ -0,0 +1,221 @@
+//! File name quoting, after GNU ls's --quoting-style
+//!
+//! Names are quoted only for display; everything else works with raw names.
+
+use crate::args::{LsArgs, QuotingStyle};
+
+/// Quoting settings after applying defaults
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quoting {
+    pub style: QuotingStyle,
+    pub hide_control_chars: bool,
+}
+
+impl Quoting {
+    /// Resolve the style from flags, then $QUOTING_STYLE, then the GNU
+    /// default: shell-escape with control characters hidden on a terminal,
+    /// literal otherwise. --zero output is raw unless a style was asked for.
+    pub fn resolve(args: &LsArgs, tty: bool) -> Self {
+        if args.zero {
+            return Quoting {
+                style: args.quoting_style.unwrap_or(QuotingStyle::Literal),
+                hide_control_chars: args.hide_control_chars.unwrap_or(false),
+            };
+        }
+
+        let style = args
+            .quoting_style
+            .or_else(|| {
+                std::env::var("QUOTING_STYLE")
+                    .ok()
+                    .and_then(|s| QuotingStyle::parse(&s))
+            })
+            .unwrap_or(if tty {
+                QuotingStyle::ShellEscape
+            } else {
+                QuotingStyle::Literal
+            });
+
+        Quoting {
+            style,
+            hide_control_chars: args.hide_control_chars.unwrap_or(tty),
+        }
+    }
+
+    /// Render a name for display
+    pub fn quote(&self, name: &str) -> String {
+        match self.style {
+            QuotingStyle::Literal => self.hide(name),
+            QuotingStyle::Shell => shell_quote(&self.hide(name), false, false),
+            QuotingStyle::ShellAlways => shell_quote(&self.hide(name), true, false),
+            QuotingStyle::ShellEscape => shell_quote(name, false, true),
+            QuotingStyle::C => format!("\"{}\"", c_escape(name, true, false)),
+            QuotingStyle::Escape => c_escape(name, false, true),
+            QuotingStyle::Locale => format!("\u{2018}{}\u{2019}", c_escape(name, false, false)),
+        }
+    }
+
+    /// Whether unquoted names should be indented by one column so they line
+    /// up with quoted ones, as GNU does for the shell styles
+    pub fn aligns_outer_quotes(&self) -> bool {
+        matches!(self.style, QuotingStyle::Shell | QuotingStyle::ShellEscape)
+    }
+
+    fn hide(&self, name: &str) -> String {
+        if self.hide_control_chars {
+            name.chars().map(|c| if c.is_control() { '?' } else { c }).collect()
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+/// Characters that make a shell treat a word specially
+fn is_shell_special(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '!' | '"' | '$' | '&' | '\'' | '(' | ')' | '*' | ';' | '<' | '=' | '>'
+            | '?' | '[' | ']' | '\\' | '^' | '`' | '|' | '{' | '}'
+    ) || c.is_control()
+}
+
+/// Quote for a POSIX shell. Names that need no quoting are left alone unless
+/// `always`; with `escape_controls`, control characters become $'\n' pieces.
+fn shell_quote(name: &str, always: bool, escape_controls: bool) -> String {
+    let needs_quoting = always
+        || name.is_empty()
+        || name.starts_with(['~', '#'])
+        || name.chars().any(is_shell_special);
+    if !needs_quoting {
+        return name.to_string();
+    }
+
+    let has_controls = escape_controls && name.chars().any(char::is_control);
+
+    // A lone apostrophe reads better inside double quotes
+    if name.contains('\'')
+        && !has_controls
+        && !name.contains(['"', '$', '`', '\\', '!'])
+    {
+        return format!("\"{}\"", name);
+    }
+
+    let mut result = String::new();
+    let mut run = String::new();
+    let mut controls = String::new();
+
+    let flush_run = |result: &mut String, run: &mut String| {
+        if !run.is_empty() {
+            result.push('\'');
+            result.push_str(run);
+            result.push('\'');
+            run.clear();
+        }
+    };
+    let flush_controls = |result: &mut String, controls: &mut String| {
+        if !controls.is_empty() {
+            result.push_str("$'");
+            result.push_str(controls);
+            result.push('\'');
+            controls.clear();
+        }
+    };
+
+    for c in name.chars() {
+        if c == '\'' {
+            flush_run(&mut result, &mut run);
+            flush_controls(&mut result, &mut controls);
+            result.push_str("\\'");
+        } else if escape_controls && c.is_control() {
+            flush_run(&mut result, &mut run);
+            push_c_escape(&mut controls, c);
+        } else {
+            flush_controls(&mut result, &mut controls);
+            run.push(c);
+        }
+    }
+    flush_run(&mut result, &mut run);
+    flush_controls(&mut result, &mut controls);
+
+    if result.is_empty() {
+        result.push_str("''");
+    }
+    result
+}
+
+/// Backslash-escape a name as C would. `quotes` escapes double quotes (for
+/// the "c" style), `spaces` escapes spaces (for the "escape" style).
+fn c_escape(name: &str, quotes: bool, spaces: bool) -> String {
+    let mut result = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' if quotes => result.push_str("\\\""),
+            ' ' if spaces => result.push_str("\\ "),
+            c if c.is_control() => push_c_escape(&mut result, c),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn push_c_escape(out: &mut String, c: char) {
+    match c {
+        '\x07' => out.push_str("\\a"),
+        '\x08' => out.push_str("\\b"),
+        '\x0c' => out.push_str("\\f"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\x0b' => out.push_str("\\v"),
+        c => {
+            let mut buf = [0u8; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("\\{:03o}", byte));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(style: QuotingStyle, name: &str) -> String {
+        Quoting {
+            style,
+            hide_control_chars: false,
+        }
+        .quote(name)
+    }
+
+    #[test]
+    fn test_shell_styles() {
+        assert_eq!(quote(QuotingStyle::Shell, "plain.txt"), "plain.txt");
+        assert_eq!(quote(QuotingStyle::Shell, "my file"), "'my file'");
+        assert_eq!(quote(QuotingStyle::Shell, "don't"), "\"don't\"");
+        assert_eq!(quote(QuotingStyle::Shell, "it's $5"), "'it'\\''s $5'");
+        assert_eq!(quote(QuotingStyle::ShellAlways, "plain.txt"), "'plain.txt'");
+        assert_eq!(quote(QuotingStyle::ShellEscape, "a\nb"), "'a'$'\\n''b'");
+        assert_eq!(quote(QuotingStyle::ShellEscape, "\t\n"), "$'\\t\\n'");
+        assert_eq!(quote(QuotingStyle::ShellEscape, ""), "''");
+    }
+
+    #[test]
+    fn test_c_and_escape_styles() {
+        assert_eq!(quote(QuotingStyle::C, "say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote(QuotingStyle::C, "tab\there"), "\"tab\\there\"");
+        assert_eq!(quote(QuotingStyle::Escape, "my file\x01"), "my\\ file\\001");
+        assert_eq!(quote(QuotingStyle::Locale, "a b"), "\u{2018}a b\u{2019}");
+    }
+
+    #[test]
+    fn test_literal_hides_control_chars() {
+        let quoting = Quoting {
+            style: QuotingStyle::Literal,
+            hide_control_chars: true,
+        };
+        assert_eq!(quoting.quote("bad\nname"), "bad?name");
+        assert_eq!(quote(QuotingStyle::Literal, "my file"), "my file");
+    }
+}
@@ -0,0 +1,128 @@
+//! Which cmd and PowerShell to run
+//!
+//! PowerShell 7 (pwsh.exe) starts faster than Windows PowerShell and is
+//! preferred when it's on PATH. LS_WRAPPER_POWERSHELL names a PowerShell
+//! to use instead. cmd is whatever %COMSPEC% says, as for any program that
+//! runs a command line. LS_WRAPPER_BACKEND (cmd, powershell or builtin)
+//! picks the backend as the matching flag would, when no flag does.
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::execute::Backend;
+
+/// A shell program, and why it was picked
+#[derive(Debug, PartialEq)]
+pub struct Interpreter {
+    pub program: PathBuf,
+    pub reason: String,
+}
+
+pub fn powershell() -> Interpreter {
+    find_powershell(&|name| env::var(name).ok())
+}
+
+pub fn cmd() -> Interpreter {
+    find_cmd(&|name| env::var(name).ok())
+}
+
+/// The backend LS_WRAPPER_BACKEND asks for, if it names one. `env` looks
+/// up environment variables.
+pub fn backend_override(env: &dyn Fn(&str) -> Option<String>) -> Option<(Backend, String)> {
+    let value = env("LS_WRAPPER_BACKEND")?;
+    let backend = match value.to_lowercase().as_str() {
+        "cmd" => Backend::Cmd,
+        "powershell" | "pwsh" | "ps" => Backend::PowerShell,
+        "builtin" | "native" => Backend::Native,
+        _ => return None,
+    };
+    Some((backend, format!("LS_WRAPPER_BACKEND={}", value)))
+}
+
+fn find_powershell(env: &dyn Fn(&str) -> Option<String>) -> Interpreter {
+    if let Some(program) = env("LS_WRAPPER_POWERSHELL").filter(|p| !p.is_empty()) {
+        return Interpreter {
+            program: PathBuf::from(program),
+            reason: "set by LS_WRAPPER_POWERSHELL".to_string(),
+        };
+    }
+    let search = env("PATH").unwrap_or_default();
+    for name in ["pwsh", "powershell"] {
+        let name = format!("{}{}", name, env::consts::EXE_SUFFIX);
+        if let Some(program) = on_path(&name, &search) {
+            return Interpreter {
+                program,
+                reason: format!("{} found on PATH", name),
+            };
+        }
+    }
+    Interpreter {
+        program: PathBuf::from("powershell.exe"),
+        reason: "neither pwsh nor powershell found on PATH".to_string(),
+    }
+}
+
+fn find_cmd(env: &dyn Fn(&str) -> Option<String>) -> Interpreter {
+    match env("COMSPEC").filter(|p| !p.is_empty()) {
+        Some(program) => Interpreter {
+            program: PathBuf::from(program),
+            reason: "from %COMSPEC%".to_string(),
+        },
+        None => Interpreter {
+            program: PathBuf::from("cmd.exe"),
+            reason: "%COMSPEC% is not set".to_string(),
+        },
+    }
+}
+
+/// The first directory on `search` holding the file `name`
+fn on_path(name: &str, search: &str) -> Option<PathBuf> {
+    env::split_paths(search)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_find_powershell() {
+        let dir = TempDir::new("shells");
+        let (old, new) = (dir.join("old"), dir.join("new"));
+        fs::create_dir_all(&old).unwrap();
+        fs::create_dir_all(&new).unwrap();
+        let exe = |name: &str| format!("{}{}", name, env::consts::EXE_SUFFIX);
+        fs::write(old.join(exe("powershell")), "").unwrap();
+        let search = env::join_paths([&old, &new]).unwrap().into_string().unwrap();
+
+        // Windows PowerShell alone
+        let path = |name: &str| (name == "PATH").then(|| search.clone());
+        let found = find_powershell(&path);
+        assert_eq!(found.program, old.join(exe("powershell")));
+
+        // pwsh wins wherever it is on PATH
+        fs::write(new.join(exe("pwsh")), "").unwrap();
+        let found = find_powershell(&path);
+        assert_eq!(found.program, new.join(exe("pwsh")));
+        assert_eq!(found.reason, format!("{} found on PATH", exe("pwsh")));
+
+        let chosen = |name: &str| match name {
+            "LS_WRAPPER_POWERSHELL" => Some(r"D:\tools\pwsh-preview.exe".to_string()),
+            _ => path(name),
+        };
+        assert_eq!(find_powershell(&chosen).program, PathBuf::from(r"D:\tools\pwsh-preview.exe"));
+
+        let nothing = find_powershell(&|_| None);
+        assert_eq!(nothing.program, PathBuf::from("powershell.exe"));
+    }
+
+    #[test]
+    fn test_find_cmd() {
+        let comspec = |name: &str| (name == "COMSPEC").then(|| r"C:\Windows\System32\cmd.exe".to_string());
+        assert_eq!(find_cmd(&comspec).program, PathBuf::from(r"C:\Windows\System32\cmd.exe"));
+        assert_eq!(find_cmd(&|_| None).program, PathBuf::from("cmd.exe"));
+    }
+}
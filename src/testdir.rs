@@ -0,0 +1,40 @@
+//! Scratch directories for tests
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory under the system temp dir, removed again when
+/// dropped, even by a failing test
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` keeps tests that run at the same time apart
+    pub fn new(name: &str) -> TempDir {
+        let dir = env::temp_dir().join(format!("ls-wrapper-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
@@ -2,57 +2,530 @@
 //!
 //! Translates Unix ls arguments into equivalent Windows dir or PowerShell commands.
 
-use crate::args::LsArgs;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::args::{ColorOption, LsArgs, OutputFormat};
+use crate::columns::{Column, PS_SHORT_NAME_FUNCTION};
+use crate::glob;
+use crate::hyperlink;
+use crate::paths::{operand_path, to_windows_path, PathForm};
 
 #[derive(Debug, Clone)]
 pub struct Translation {
     pub cmd_command: String,
     pub powershell_command: String,
     pub description: String,
+    pub cmd_warnings: Vec<String>,        // where dir won't match what ls would
+    pub powershell_warnings: Vec<String>, // likewise for Get-ChildItem
+    pub path_forms: Vec<String>,          // operands Windows reads differently
+    pub fidelity: Vec<FlagFidelity>,      // how well each backend does each flag
+}
+
+/// How faithfully a backend carries out a flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fidelity {
+    Exact,
+    Approximate,
+    Unsupported,
+}
+
+impl Fidelity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fidelity::Exact => "exact",
+            Fidelity::Approximate => "approximate",
+            Fidelity::Unsupported => "unsupported",
+        }
+    }
 }
 
-pub fn translate(args: &LsArgs) -> Translation {
-    let cmd_command = build_dir_command(args);
-    let powershell_command = build_powershell_command(args);
+/// One flag given, rated for each shell
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagFidelity {
+    pub flag: &'static str,
+    pub cmd: Fidelity,
+    pub powershell: Fidelity,
+}
+
+/// `tty` says whether the output counts as going to a terminal, for the
+/// flags whose `auto` setting depends on it
+pub fn translate(args: &LsArgs, tty: bool) -> Translation {
+    let plan = PathPlan::new(args);
+    let mut cmd_rated = Rated::default();
+    let mut ps_rated = Rated::default();
+    let cmd_command = build_dir_command(args, &plan, &mut cmd_rated);
+    let powershell_command = build_powershell_command(args, &plan, tty, &mut ps_rated);
     let description = build_description(args);
 
+    // A flag a builder didn't deal with is one its shell can't carry out
+    let fidelity: Vec<FlagFidelity> = given_flags(args)
+        .into_iter()
+        .map(|flag| FlagFidelity {
+            flag,
+            cmd: cmd_rated.of(flag),
+            powershell: ps_rated.of(flag),
+        })
+        .collect();
+
+    // Flags a shell drops come first, then what its patterns get wrong
+    let mut cmd_warnings = Vec::new();
+    let mut powershell_warnings = Vec::new();
+    for rating in &fidelity {
+        if rating.cmd == Fidelity::Unsupported {
+            cmd_warnings.push(dropped(rating.flag, "cmd"));
+        }
+        if rating.powershell == Fidelity::Unsupported {
+            powershell_warnings.push(dropped(rating.flag, "PowerShell"));
+        }
+    }
+    cmd_warnings.extend(plan.cmd_warnings);
+    powershell_warnings.extend(plan.ps_warnings);
+
     Translation {
         cmd_command,
         powershell_command,
         description,
+        cmd_warnings,
+        powershell_warnings,
+        path_forms: describe_paths(args),
+        fidelity,
+    }
+}
+
+/// How faithfully a builder carried out the flags it dealt with, counting
+/// the reformatting execute does on its output. Only flags that were given
+/// are looked up, so a builder can rate what it would do without checking.
+#[derive(Debug, Default)]
+struct Rated(Vec<(&'static str, Fidelity)>);
+
+impl Rated {
+    fn rate(&mut self, flag: &'static str, fidelity: Fidelity) {
+        self.0.push((flag, fidelity));
+    }
+
+    fn of(&self, flag: &str) -> Fidelity {
+        self.0
+            .iter()
+            .find(|(rated, _)| *rated == flag)
+            .map_or(Fidelity::Unsupported, |&(_, fidelity)| fidelity)
+    }
+}
+
+/// Each flag given that changes what ls prints
+fn given_flags(args: &LsArgs) -> Vec<&'static str> {
+    let text = !args.output.is_structured();
+    let sorted = !args.no_sort;
+    let hides = !(args.all || args.almost_all); // --hide is off with -a and -A
+    let flags = [
+        ("-a", args.all),
+        ("-A", args.almost_all),
+        ("-l", args.long_format && text),
+        ("--long-columns", args.long_columns.is_some() && text),
+        ("-h", args.human_readable),
+        ("-1", args.one_per_line && !args.long_format),
+        ("-C", args.columns && !args.long_format),
+        ("-x", args.across && !args.long_format),
+        ("-m", args.commas && !args.long_format),
+        ("-R", args.recursive),
+        ("-d", args.directory),
+        ("-F", args.classify),
+        ("-s", args.show_size),
+        ("-L", args.dereference),
+        ("-H", args.dereference_command_line && !args.dereference),
+        ("-t", args.sort_by_time && sorted),
+        ("-S", args.sort_by_size && sorted),
+        ("-r", args.reverse && sorted),
+        ("-U", args.no_sort),
+        ("--color", args.color == ColorOption::Always),
+        ("--quoting-style", (args.quoting_style.is_some() || args.hide_control_chars.is_some()) && text),
+        ("--hyperlink", hyperlinks_given(args) && text),
+        ("--zero", args.zero),
+        ("-I", !args.ignore.is_empty()),
+        ("--hide", hides && !args.hide.is_empty()),
+        ("--gitignore", args.gitignore),
+        ("--json", args.output == OutputFormat::Json),
+        ("--ndjson", args.output == OutputFormat::Ndjson),
+        ("--format", matches!(args.output, OutputFormat::Csv | OutputFormat::Tsv)),
+        ("--shell-glob", args.shell_glob),
+    ];
+    flags.into_iter().filter(|&(_, given)| given).map(|(flag, _)| flag).collect()
+}
+
+fn hyperlinks_given(args: &LsArgs) -> bool {
+    args.hyperlink.is_some_and(|h| h != ColorOption::Never)
+}
+
+/// Exact, unless the pattern translation left warnings
+fn warned(warnings: &[String]) -> Fidelity {
+    if warnings.is_empty() {
+        Fidelity::Exact
+    } else {
+        Fidelity::Approximate
+    }
+}
+
+/// The warning for a flag a shell can't carry out
+fn dropped(flag: &str, shell: &str) -> String {
+    match (flag, shell) {
+        ("-d", "cmd") => "-d has no cmd equivalent; /AD lists the directories inside instead".to_string(),
+        ("-d", _) => format!("-d has no {} equivalent; -Directory lists the directories inside instead", shell),
+        ("--quoting-style", _) => format!("--quoting-style can't reach names in {}'s columns; they print raw", shell),
+        _ => format!("{} has no {} equivalent; ignored", flag, shell),
+    }
+}
+
+/// Whether execute can find the names in a shell's text output to quote
+/// them: alone on their lines, or ending the rows of a table, but not laid
+/// out in columns
+pub fn names_quotable(args: &LsArgs, cmd: bool) -> bool {
+    let name_last = args.long_columns.as_ref().is_none_or(|c| c.last() == Some(&Column::Name));
+    let table_names = cmd || !args.long_format || name_last;
+    !args.output.is_structured() && !names_in_columns(args, cmd) && (names_listed(args, cmd) || table_names)
+}
+
+/// Whether a shell prints the names alone, one to a line
+pub fn names_listed(args: &LsArgs, cmd: bool) -> bool {
+    if cmd {
+        dir_bare(args)
+    } else {
+        ps_bare(args) || ((args.one_per_line || args.commas) && !args.long_format)
     }
 }
 
-fn build_dir_command(args: &LsArgs) -> String {
+/// Whether a shell lays the names out in columns, /W or Format-Wide
+pub fn names_in_columns(args: &LsArgs, cmd: bool) -> bool {
+    (args.columns || args.across) && !args.long_format && !names_listed(args, cmd)
+}
+
+/// Whether dir runs with /B, printing names alone
+pub fn dir_bare(args: &LsArgs) -> bool {
+    // Structured output is built from the bare names by stat-ing each one.
+    // With --gitignore the names are filtered afterwards, which needs /B
+    // too, unless -l wants dir's long form; dir can't skip ignored files then.
+    let names = args.gitignore || args.one_per_line || args.commas;
+    args.output.is_structured() || (names && !args.long_format)
+}
+
+/// Whether Get-ChildItem prints full paths alone, for execute to filter for
+/// --gitignore. PowerShell's table can't be filtered, so not with -l.
+pub fn ps_bare(args: &LsArgs) -> bool {
+    args.gitignore && (args.output.is_structured() || !args.long_format)
+}
+
+/// Each operand that isn't a plain relative path, and what it became
+fn describe_paths(args: &LsArgs) -> Vec<String> {
+    args.paths
+        .iter()
+        .filter_map(|operand| {
+            let operand = operand.to_string_lossy();
+            let path = to_windows_path(&operand);
+            let form = PathForm::of(&path);
+            if form == PathForm::Relative && path == operand.replace('/', "\\") {
+                return None;
+            }
+            Some(format!("{} -> {} ({})", operand, path, form.describe()))
+        })
+        .collect()
+}
+
+/// Operands as the Windows backends take them. With --shell-glob, patterns
+/// are left for the backend and mapped onto its nearest constructs: several
+/// paths for `{a,b}`, /S or -Recurse with -Filter/-Include for `**`, and a
+/// Where-Object regex for what PowerShell's wildcards can't say.
+#[derive(Debug, Default)]
+struct PathPlan {
+    cmd_paths: Vec<String>,
+    cmd_recurse: bool,
+    cmd_warnings: Vec<String>,
+    ps_paths: Vec<String>,
+    ps_filter: Option<String>,
+    ps_include: Vec<String>,
+    ps_recurse: bool,
+    ps_match: Vec<String>, // path regexes; items matching none are dropped
+    ps_warnings: Vec<String>,
+}
+
+/// One brace alternative of a pattern, split where `**` recurses
+#[derive(Debug)]
+struct GlobPart {
+    base: Option<String>, // directory part, '/'-separated
+    tail: String,         // what follows **, or the last component
+    recurse: bool,
+}
+
+impl GlobPart {
+    fn parse(pattern: &str) -> GlobPart {
+        let components: Vec<&str> = pattern.split('/').collect();
+        let join = |parts: &[&str]| {
+            let joined = parts.join("/");
+            if joined.is_empty() { "/".to_string() } else { joined }
+        };
+        match components.iter().position(|&c| c == "**") {
+            Some(i) => GlobPart {
+                base: (i > 0).then(|| join(&components[..i])),
+                tail: match components[i + 1..].join("/") {
+                    tail if tail.is_empty() => "*".to_string(),
+                    tail => tail,
+                },
+                recurse: true,
+            },
+            None => {
+                let last = components.len() - 1;
+                GlobPart {
+                    base: (last > 0).then(|| join(&components[..last])),
+                    tail: components[last].to_string(),
+                    recurse: false,
+                }
+            }
+        }
+    }
+
+    fn leaf(&self) -> &str {
+        self.tail.rsplit('/').next().unwrap_or(&self.tail)
+    }
+
+    /// The whole pattern again, without the **
+    fn path(&self, leaf: &str) -> String {
+        match &self.base {
+            Some(base) if base.ends_with('/') => format!("{}{}", base, leaf),
+            Some(base) => format!("{}/{}", base, leaf),
+            None => leaf.to_string(),
+        }
+    }
+}
+
+impl PathPlan {
+    fn new(args: &LsArgs) -> PathPlan {
+        let mut plan = PathPlan::default();
+
+        // The shells take commands as text, so names that aren't valid
+        // Unicode can only be approximated here; the built-in walker runs
+        // for those instead. With -H each link named is swapped for what it
+        // points to, since neither shell can be told to follow just those.
+        let operands: Vec<String> = args
+            .paths
+            .iter()
+            .map(|p| match args.dereference_command_line {
+                true => follow_operand_link(p).to_string_lossy().into_owned(),
+                false => p.to_string_lossy().into_owned(),
+            })
+            .collect();
+
+        // Each operand's brace alternatives, in order; None for a plain path
+        let mut parts: Vec<(&str, Option<GlobPart>)> = Vec::new();
+        for operand in &operands {
+            let alternatives = glob::expand_braces(operand);
+            if !args.shell_glob || (alternatives.len() == 1 && !glob::has_wildcards(operand)) {
+                parts.push((operand, None));
+                continue;
+            }
+            for alternative in alternatives {
+                let unixy = if cfg!(windows) { alternative.replace('\\', "/") } else { alternative };
+                parts.push((operand, Some(GlobPart::parse(&unixy))));
+            }
+        }
+
+        plan.plan_cmd(&parts);
+        plan.plan_powershell(&parts);
+        plan
+    }
+
+    fn plan_cmd(&mut self, parts: &[(&str, Option<GlobPart>)]) {
+        for (operand, part) in parts {
+            let Some(part) = part else {
+                self.cmd_paths.push(to_windows_path(operand));
+                continue;
+            };
+            let leaf = glob::classes_to_any(part.leaf(), false);
+            if leaf != part.leaf() {
+                self.warn_cmd(operand, "dir has no [...] classes, so each matches any one character");
+            }
+            if part.base.as_deref().is_some_and(glob::has_wildcards) {
+                self.warn_cmd(operand, "dir can't match wildcards in directory names");
+            }
+            if part.tail.contains('/') {
+                self.warn_cmd(operand, "dir /S matches the name at any depth, whatever directories lead to it");
+            }
+            self.cmd_recurse |= part.recurse;
+            self.cmd_paths.push(to_windows_path(&part.path(&leaf)));
+        }
+
+        if self.cmd_recurse {
+            for (operand, _) in parts.iter().filter(|(_, p)| !p.as_ref().is_some_and(|p| p.recurse)) {
+                self.warn_cmd(operand, "dir /S applies to every operand once one uses **");
+            }
+        }
+    }
+
+    fn plan_powershell(&mut self, parts: &[(&str, Option<GlobPart>)]) {
+        let globs = || parts.iter().filter_map(|(operand, p)| Some((*operand, p.as_ref()?)));
+
+        // PowerShell's wildcards have classes, but not negated ones
+        let negated = globs().any(|(_, p)| glob::classes_to_any(&p.tail, true) != p.tail);
+        let nested = globs().any(|(_, p)| p.tail.contains('/'));
+        self.ps_recurse = globs().any(|(_, p)| p.recurse);
+
+        if !self.ps_recurse {
+            // -Path expands *, ? and [...] by itself
+            for (operand, part) in parts {
+                let Some(part) = part else {
+                    self.ps_paths.push(to_windows_path(operand));
+                    continue;
+                };
+                if part.base.as_deref().is_some_and(|b| glob::classes_to_any(b, true) != b) {
+                    self.warn_ps(operand, "negated classes in directory names match any character");
+                }
+                let path = part.path(&glob::classes_to_any(&part.tail, true));
+                self.ps_paths.push(to_windows_path(&path));
+            }
+        } else {
+            // ** becomes -Recurse below each base, looking for the names
+            let mut pairs = 0;
+            let mut leaves: Vec<String> = Vec::new();
+            for (operand, part) in parts {
+                let base = match part {
+                    Some(part) if part.recurse => part.base.as_deref().unwrap_or("."),
+                    _ => {
+                        self.warn_ps(operand, "-Recurse and -Include apply to every operand once one uses **");
+                        part.as_ref().and_then(|p| p.base.as_deref()).unwrap_or(operand)
+                    }
+                };
+                let base = to_windows_path(base);
+                if !self.ps_paths.contains(&base) {
+                    self.ps_paths.push(base);
+                }
+                if let Some(part) = part {
+                    let leaf = glob::classes_to_any(part.leaf(), true);
+                    if !leaves.contains(&leaf) {
+                        leaves.push(leaf);
+                    }
+                    pairs += 1;
+                }
+            }
+            if self.ps_paths.len() * leaves.len() > pairs {
+                self.warn_ps(parts[0].0, "-Include pairs every directory with every name pattern");
+            }
+
+            // -Filter only knows * and ?
+            if leaves.len() == 1 && !leaves[0].contains('[') {
+                self.ps_filter = leaves.pop();
+            } else {
+                self.ps_include = leaves;
+            }
+        }
+
+        // Negated classes, and directories between ** and the name, need
+        // the pattern checked as a regex
+        if negated || nested {
+            for (operand, part) in parts {
+                match part {
+                    Some(part) => {
+                        let tail = if part.recurse { &part.tail } else { part.leaf() };
+                        self.ps_match.push(format!("(^|/){}$", glob::to_regex(tail)));
+                    }
+                    None => self.warn_ps(operand, "the Where-Object filter applies to every operand"),
+                }
+            }
+        }
+    }
+
+    fn warn_cmd(&mut self, operand: &str, reason: &str) {
+        let warning = format!("'{}': {}", operand, reason);
+        if !self.cmd_warnings.contains(&warning) {
+            self.cmd_warnings.push(warning);
+        }
+    }
+
+    fn warn_ps(&mut self, operand: &str, reason: &str) {
+        let warning = format!("'{}': {}", operand, reason);
+        if !self.ps_warnings.contains(&warning) {
+            self.ps_warnings.push(warning);
+        }
+    }
+}
+
+/// What an operand that's a link points to, or the operand itself
+fn follow_operand_link(operand: &Path) -> PathBuf {
+    match fs::read_link(operand_path(operand)) {
+        Ok(target) => operand.parent().map(|parent| parent.join(&target)).unwrap_or(target),
+        Err(_) => operand.to_path_buf(),
+    }
+}
+
+/// Quote a path for cmd or PowerShell if it has spaces
+fn quote_path(path: &str) -> String {
+    if path.contains(' ') {
+        format!("\"{}\"", path)
+    } else {
+        path.to_string()
+    }
+}
+
+fn build_dir_command(args: &LsArgs, plan: &PathPlan, rated: &mut Rated) -> String {
+    use Fidelity::*;
+
     let mut cmd = String::from("dir");
     let mut flags = Vec::new();
+    let bare = dir_bare(args);
 
-    // /A - show hidden files (like -a)
+    // /A - show hidden files (like -a). dir lists . and .. except in its
+    // bare form.
     if args.all || args.almost_all {
         flags.push("/A");
     }
+    rated.rate("-a", if bare { Approximate } else { Exact });
+    rated.rate("-A", if bare { Exact } else { Approximate });
 
-    // /S - recursive (like -R)
-    if args.recursive {
+    // /S - recursive (like -R, and ** patterns)
+    if args.recursive || plan.cmd_recurse {
         flags.push("/S");
     }
+    rated.rate("-R", Approximate);
 
-    // /AD - directories only (like -d, sort of)
+    // /AD - directories only (like -d, sort of). It lists the directories
+    // inside rather than the operand itself, so -d isn't carried out.
     if args.directory {
         flags.push("/AD");
     }
 
     // /B - bare format (like -1)
     // Only use /B if we want simple output and not long format
-    if args.one_per_line && !args.long_format {
+    if bare {
         flags.push("/B");
+        rated.rate("-1", Exact);
+        // execute quotes, hyperlinks, filters, NUL-terminates or joins the
+        // names with commas, or turns them into records
+        rated.rate("-m", Exact);
+        rated.rate("--gitignore", Exact);
+        rated.rate("--json", Exact);
+        rated.rate("--ndjson", Exact);
+        rated.rate("--format", Exact);
+    } else if names_in_columns(args, true) {
+        // /W - wide format (like -x, but every column as wide as the widest)
+        flags.push("/W");
+        rated.rate("-C", Approximate);
+        rated.rate("-x", Approximate);
+    } else {
+        // dir's long form has no mode column and adds a header and totals
+        rated.rate("-l", Approximate);
     }
+    // execute quotes and links the names in /B and the long form, but
+    // can't pick them out of /W's columns
+    if names_quotable(args, true) {
+        rated.rate("--quoting-style", Exact);
+    }
+    if !names_in_columns(args, true) {
+        rated.rate("--hyperlink", Exact);
+    }
+    rated.rate("--zero", Exact);
 
     // Sorting options
     // Note: ls shows newest/largest FIRST by default, dir shows oldest/smallest first
     // So we invert: ls -t = dir /O-D (descending date)
     if args.no_sort {
         // No sort flag for dir, it's default
+        rated.rate("-U", Exact);
     } else if args.sort_by_time {
         if args.reverse {
             flags.push("/OD"); // -tr = oldest first (ascending)
@@ -68,6 +541,9 @@ fn build_dir_command(args: &LsArgs) -> String {
     } else if args.reverse {
         flags.push("/O-N"); // Reverse name
     }
+    for flag in ["-t", "-S", "-r"] {
+        rated.rate(flag, Exact);
+    }
 
     // Build command string
     for flag in flags {
@@ -75,61 +551,111 @@ fn build_dir_command(args: &LsArgs) -> String {
         cmd.push_str(flag);
     }
 
-    // Add paths
-    for path in &args.paths {
+    // Add paths, with -H's links already followed
+    for path in &plan.cmd_paths {
         cmd.push(' ');
-        let win_path = to_windows_path(path);
-        if win_path.contains(' ') {
-            cmd.push('"');
-            cmd.push_str(&win_path);
-            cmd.push('"');
-        } else {
-            cmd.push_str(&win_path);
-        }
+        cmd.push_str(&quote_path(path));
     }
+    rated.rate("-H", Approximate);
+    rated.rate("--shell-glob", warned(&plan.cmd_warnings));
 
     cmd
 }
 
-fn build_powershell_command(args: &LsArgs) -> String {
+fn build_powershell_command(args: &LsArgs, plan: &PathPlan, tty: bool, rated: &mut Rated) -> String {
+    use Fidelity::*;
+
     let mut cmd = String::from("Get-ChildItem");
     let mut params = Vec::new();
 
-    // -Force - show hidden files (like -a)
+    // -Force - show hidden files (like -A; it never lists . and ..)
     if args.all || args.almost_all {
         params.push("-Force");
     }
+    rated.rate("-a", Approximate);
+    rated.rate("-A", Exact);
 
-    // -Recurse (like -R)
-    if args.recursive {
+    // -Recurse (like -R, and ** patterns)
+    if args.recursive || plan.ps_recurse {
         params.push("-Recurse");
     }
+    rated.rate("-R", Approximate);
 
-    // -Directory (like -d)
+    // -Directory (like -d). Like /AD it lists the directories inside
+    // rather than the operand itself, so -d isn't carried out.
     if args.directory {
         params.push("-Directory");
     }
 
+    // -FollowSymlink (like -L with -R, PowerShell 6+)
+    // Without it, recursion doesn't descend into linked directories. Links
+    // given as operands are listed under their own names.
+    if args.dereference && args.recursive {
+        params.push("-FollowSymlink");
+    }
+    rated.rate("-L", Approximate);
+
     // Add parameters
     for param in params {
         cmd.push(' ');
         cmd.push_str(param);
     }
 
-    // Add paths
-    for path in &args.paths {
-        cmd.push_str(" -Path ");
-        let win_path = to_windows_path(path);
-        if win_path.contains(' ') {
-            cmd.push('"');
-            cmd.push_str(&win_path);
-            cmd.push('"');
-        } else {
-            cmd.push_str(&win_path);
-        }
+    // Add paths. -Path takes a list, but only once.
+    let paths: Vec<String> = plan.ps_paths.iter().map(|p| quote_path(p)).collect();
+    cmd.push_str(" -Path ");
+    cmd.push_str(&paths.join(","));
+    rated.rate("-H", Approximate);
+    rated.rate("--shell-glob", warned(&plan.ps_warnings));
+
+    // -Filter is applied by the file system, so it's the fast one
+    if let Some(filter) = &plan.ps_filter {
+        cmd.push_str(" -Filter ");
+        cmd.push_str(&quote_path(filter));
+    }
+    if !plan.ps_include.is_empty() {
+        let patterns: Vec<String> = plan.ps_include.iter().map(|p| format!("'{}'", p.replace('\'', "''"))).collect();
+        cmd.push_str(" -Include ");
+        cmd.push_str(&patterns.join(","));
     }
 
-    // Add sorting
+    // -Exclude (like -I, and --hide without -a)
+    let git_dir = ".git".to_string();
+    let mut excluded: Vec<&String> = args.ignore.iter().collect();
+    if !(args.all || args.almost_all) {
+        excluded.extend(&args.hide);
+    }
+    if args.gitignore {
+        excluded.push(&git_dir);
+    }
+    if !excluded.is_empty() {
+        let patterns: Vec<String> = excluded.iter().map(|p| format!("'{}'", p.replace('\'', "''"))).collect();
+        cmd.push_str(" -Exclude ");
+        cmd.push_str(&patterns.join(","));
+    }
+    rated.rate("-I", Exact);
+    rated.rate("--hide", Exact);
+
+    // What the wildcards couldn't express, as regexes on the path
+    if !plan.ps_match.is_empty() {
+        let tests: Vec<String> = plan
+            .ps_match
+            .iter()
+            .map(|r| format!("$p -match '{}'", r.replace('\'', "''")))
+            .collect();
+        cmd.push_str(&format!(
+            " | Where-Object {{ $p = $_.FullName -replace '\\\\', '/'; {} }}",
+            tests.join(" -or ")
+        ));
+    }
+
+    // Add sorting. Get-ChildItem -Recurse sorts the whole listing, not
+    // each directory.
+    let sorted = if args.recursive { Approximate } else { Exact };
+    for flag in ["-t", "-S", "-r"] {
+        rated.rate(flag, sorted);
+    }
+    rated.rate("-U", Exact);
     if !args.no_sort {
         if args.sort_by_time {
             cmd.push_str(" | Sort-Object LastWriteTime");
@@ -146,16 +672,179 @@ fn build_powershell_command(args: &LsArgs) -> String {
         }
     }
 
+    // Structured output replaces all formatting
+    rated.rate("--zero", Exact);
+    for flag in ["--json", "--ndjson", "--format"] {
+        rated.rate(flag, Exact);
+    }
+
+    // --gitignore: write full paths for execute to filter with the built-in
+    // matcher, then show by name or turn into records, as it does dir /B's
+    if ps_bare(args) {
+        cmd.push_str(" | ForEach-Object { $_.FullName }");
+        for flag in ["--gitignore", "-1", "-m", "--hyperlink", "--quoting-style"] {
+            rated.rate(flag, Exact);
+        }
+        return cmd;
+    }
+    match args.output {
+        OutputFormat::Json => {
+            cmd.push_str(PS_JSON_OBJECT);
+            return format!("ConvertTo-Json -Compress -InputObject @({})", cmd);
+        }
+        OutputFormat::Ndjson => {
+            cmd.push_str(PS_JSON_OBJECT);
+            cmd.push_str(" | ForEach-Object { $_ | ConvertTo-Json -Compress }");
+            return cmd;
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let columns = args.table_columns.clone().unwrap_or_else(|| Column::DEFAULT.to_vec());
+            let properties: Vec<&str> = columns.iter().map(|c| c.ps_property()).collect();
+            cmd.push_str(" | Select-Object ");
+            cmd.push_str(&properties.join(", "));
+            cmd.push_str(&ps_table_rows(&columns, args.output == OutputFormat::Tsv));
+            return with_helpers(cmd, &columns);
+        }
+        OutputFormat::Text => {}
+    }
+    if names_quotable(args, false) {
+        rated.rate("--quoting-style", Exact);
+    }
+
     // Format output for -l equivalent
+    let links = hyperlink::enabled(args, tty);
+    let name = ps_display_name(args, links);
     if args.long_format {
-        cmd.push_str(" | Format-Table Mode, LastWriteTime, Length, Name -AutoSize");
-    } else if args.one_per_line {
-        cmd.push_str(" | Select-Object -ExpandProperty Name");
+        let columns = args.long_columns.clone().unwrap_or_else(|| {
+            vec![Column::Perms, Column::Mtime, Column::Size, Column::Name]
+        });
+        let properties: Vec<String> = columns
+            .iter()
+            .map(|column| match (column, &name) {
+                (Column::Name, Some(script)) => format!("@{{N='Name';E={{{}$n}}}}", script),
+                _ => column.ps_long_property().to_string(),
+            })
+            .collect();
+        cmd.push_str(" | Format-Table ");
+        cmd.push_str(&properties.join(", "));
+        cmd.push_str(" -AutoSize");
+        rated.rate("-l", Exact);
+        rated.rate("--long-columns", Exact);
+        rated.rate("--hyperlink", Exact);
+        return with_helpers(cmd, &columns);
+    } else if args.zero {
+        // Write names raw so ones containing newlines survive intact
+        let script = name.unwrap_or_else(|| "$n = $_.Name; ".to_string());
+        cmd.push_str(&format!(
+            " | ForEach-Object {{ {}[Console]::Out.Write($n + [char]0) }}",
+            script
+        ));
+    } else if names_listed(args, false) {
+        match &name {
+            Some(script) => cmd.push_str(&format!(" | ForEach-Object {{ {}$n }}", script)),
+            None => cmd.push_str(" | Select-Object -ExpandProperty Name"),
+        }
+    } else if names_in_columns(args, false) {
+        // Format-Wide fills its rows across, like -x
+        cmd.push_str(" | Format-Wide Name -AutoSize");
+        rated.rate("-C", Approximate);
+        rated.rate("-x", Approximate);
+    }
+
+    // execute joins the names with commas for -m
+    if names_listed(args, false) {
+        rated.rate("-1", Exact);
+        rated.rate("-m", Exact);
+        rated.rate("--hyperlink", Exact);
     }
 
     cmd
 }
 
+/// Prepend any helper functions the columns' properties call
+fn with_helpers(cmd: String, columns: &[Column]) -> String {
+    if columns.contains(&Column::ShortName) {
+        format!("{}{}", PS_SHORT_NAME_FUNCTION, cmd)
+    } else {
+        cmd
+    }
+}
+
+/// Pipeline stage mapping FileSystemInfo objects onto the JSON schema
+/// (see json.rs). Hard links have a LinkType too, but are plain files.
+const PS_JSON_OBJECT: &str = " | ForEach-Object { $l = $_.LinkType -in 'SymbolicLink','Junction'; [pscustomobject]@{ \
+    version = 1; name = $_.Name; path = $_.FullName; \
+    type = if ($l) {'symlink'} elseif ($_.PSIsContainer) {'directory'} else {'file'}; \
+    size = if ($_.PSIsContainer) {0} else {$_.Length}; \
+    modified = $_.LastWriteTimeUtc.ToString(\"yyyy-MM-dd'T'HH:mm:ss'Z'\"); \
+    accessed = $_.LastAccessTimeUtc.ToString(\"yyyy-MM-dd'T'HH:mm:ss'Z'\"); \
+    created = $_.CreationTimeUtc.ToString(\"yyyy-MM-dd'T'HH:mm:ss'Z'\"); \
+    attributes = @($_.Attributes.ToString().ToLower() -split ', '); \
+    link_target = if (-not $l) {$null} elseif ($_.LinkTarget) {$_.LinkTarget} else {\"$($_.Target)\"}; \
+    hidden = [bool]($_.Attributes -band 2) -or $_.Name.StartsWith('.') } }";
+
+/// Pipeline stage writing the selected properties as CSV or TSV rows, byte
+/// for byte as csv.rs does. ConvertTo-Csv would quote every field, and
+/// Windows PowerShell has no way to stop it.
+fn ps_table_rows(columns: &[Column], tabs: bool) -> String {
+    let (separator, field, eol) = if tabs {
+        ("`t", PS_TSV_FIELD, "`n")
+    } else {
+        (",", PS_CSV_FIELD, "`r`n")
+    };
+    let header: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+    format!(
+        " | ForEach-Object -Begin {{ [Console]::Out.Write(\"{}{}\") }} \
+         -Process {{ [Console]::Out.Write((@($_.PSObject.Properties | ForEach-Object {{ {} }}) -join \"{}\") + \"{}\") }}",
+        header.join(separator),
+        eol,
+        field,
+        separator,
+        eol
+    )
+}
+
+/// A property's value as a CSV field, quoted only when it has to be
+const PS_CSV_FIELD: &str = r#"$v = "$($_.Value)"; if ($v -match '[,"\r\n]') {'"' + $v.Replace('"', '""') + '"'} else {$v}"#;
+
+/// A property's value as a TSV field, with backslash escapes
+const PS_TSV_FIELD: &str = r#""$($_.Value)" -replace '\\', '\\' -replace "`t", '\t' -replace "`n", '\n' -replace "`r", '\r'"#;
+
+/// PowerShell statements that set `$n` to the name as it should be shown,
+/// or None when the plain Name property will do
+fn ps_display_name(args: &LsArgs, links: bool) -> Option<String> {
+    // A target column shows link targets itself
+    let target_column = args
+        .long_columns
+        .as_ref()
+        .is_some_and(|c| c.contains(&Column::Target));
+    let show_targets = args.long_format && !args.dereference && !target_column;
+    if !links && !show_targets {
+        return None;
+    }
+
+    let mut script = String::from("$n = $_.Name; ");
+
+    // OSC 8 link to the file:// URI, with this machine as the host
+    if links {
+        script.push_str(
+            "$u = ([uri]$_.FullName).AbsoluteUri -replace '^file:///', \"file://$env:COMPUTERNAME/\"; \
+             $n = \"$([char]27)]8;;$u$([char]27)\\$n$([char]27)]8;;$([char]27)\\\"; ",
+        );
+    }
+
+    // Links show as "name -> target" unless -L asked to follow them.
+    // PowerShell 7 has LinkTarget, Windows PowerShell only Target.
+    if show_targets {
+        script.push_str(
+            "$t = if ($_.LinkTarget) {$_.LinkTarget} else {$_.Target}; \
+             if ($t) {$n = \"$n -> $t\"}; ",
+        );
+    }
+
+    Some(script)
+}
+
 fn build_description(args: &LsArgs) -> String {
     let mut parts = Vec::new();
 
@@ -180,6 +869,14 @@ fn build_description(args: &LsArgs) -> String {
     if args.human_readable {
         parts.push("human-readable sizes");
     }
+    if args.gitignore {
+        parts.push("skip files ignored by git");
+    }
+    if args.dereference {
+        parts.push("follow symbolic links");
+    } else if args.dereference_command_line {
+        parts.push("follow command-line symbolic links");
+    }
 
     if parts.is_empty() {
         "list directory contents".to_string()
@@ -188,40 +885,292 @@ fn build_description(args: &LsArgs) -> String {
     }
 }
 
-/// Convert Unix-style paths to Windows-style
-fn to_windows_path(path: &str) -> String {
-    let mut result = path.to_string();
-
-    // Convert forward slashes to backslashes
-    result = result.replace('/', "\\");
-
-    // Handle home directory (~)
-    if result.starts_with('~') {
-        if let Ok(home) = std::env::var("USERPROFILE") {
-            result = result.replacen('~', &home, 1);
-        }
-    }
-
-    result
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testdir::TempDir;
 
     #[test]
     fn test_basic_translation() {
         let args = LsArgs::default();
-        let trans = translate(&args);
+        let trans = translate(&args, false);
         assert!(trans.cmd_command.starts_with("dir"));
     }
 
     #[test]
     fn test_la_translation() {
-        let mut args = LsArgs::default();
-        args.long_format = true;
-        args.all = true;
-        let trans = translate(&args);
+        let args = LsArgs {
+            long_format: true,
+            all: true,
+            ..Default::default()
+        };
+        let trans = translate(&args, false);
         assert!(trans.cmd_command.contains("/A"));
     }
+
+    #[test]
+    fn test_fidelity() {
+        let rating = |argv: &[&str], flag: &str| {
+            let trans = translate(&LsArgs::parse(argv).unwrap(), false);
+            let found = trans.fidelity.iter().find(|f| f.flag == flag).cloned();
+            found.map(|f| (f.cmd, f.powershell))
+        };
+        assert_eq!(rating(&["ls", "-l"], "-l"), Some((Fidelity::Approximate, Fidelity::Exact)));
+        assert_eq!(rating(&["ls", "-l"], "-a"), None);
+        assert_eq!(rating(&["ls", "-t"], "-t"), Some((Fidelity::Exact, Fidelity::Exact)));
+        assert_eq!(rating(&["ls", "-Rt"], "-t"), Some((Fidelity::Exact, Fidelity::Approximate)));
+        assert_eq!(rating(&["ls", "-I", "*.o"], "-I"), Some((Fidelity::Unsupported, Fidelity::Exact)));
+        assert_eq!(rating(&["ls", "-1Q"], "--quoting-style"), Some((Fidelity::Exact, Fidelity::Exact)));
+        // . and .. only come from dir's long form
+        assert_eq!(rating(&["ls", "-1a"], "-a"), Some((Fidelity::Approximate, Fidelity::Approximate)));
+        assert_eq!(rating(&["ls", "-la"], "-a"), Some((Fidelity::Exact, Fidelity::Approximate)));
+        // Each builder rates what it did: both keep -l's table and so
+        // can't filter it for --gitignore
+        let long = ["ls", "-l", "--gitignore"];
+        assert_eq!(rating(&long, "-l"), Some((Fidelity::Approximate, Fidelity::Exact)));
+        assert_eq!(rating(&long, "--gitignore"), Some((Fidelity::Unsupported, Fidelity::Unsupported)));
+        assert_eq!(rating(&["ls", "--gitignore"], "--gitignore"), Some((Fidelity::Exact, Fidelity::Exact)));
+
+        // Names are quoted where they end a line, not in columns
+        let exact = Some((Fidelity::Exact, Fidelity::Exact));
+        assert_eq!(rating(&["ls", "-lQ"], "--quoting-style"), exact);
+        assert_eq!(rating(&["ls", "-Q"], "--quoting-style"), exact);
+        let columns = Some((Fidelity::Unsupported, Fidelity::Unsupported));
+        assert_eq!(rating(&["ls", "-CQ"], "--quoting-style"), columns);
+        let name_first = ["ls", "-lQ", "--long-columns=name,size"];
+        assert_eq!(rating(&name_first, "--quoting-style"), Some((Fidelity::Exact, Fidelity::Unsupported)));
+
+        // /W and Format-Wide fill across; execute joins bare names for -m
+        let approximate = Some((Fidelity::Approximate, Fidelity::Approximate));
+        assert_eq!(rating(&["ls", "-x"], "-x"), approximate);
+        assert_eq!(rating(&["ls", "-m"], "-m"), exact);
+
+        // Inexact --shell-glob translations show up as warnings too
+        let rating = rating(&["ls", "--shell-glob", "[!a]*"], "--shell-glob");
+        assert_eq!(rating, Some((Fidelity::Approximate, Fidelity::Exact)));
+    }
+
+    #[test]
+    fn test_dropped_flag_warnings() {
+        let trans = translate(&LsArgs::parse(["ls", "-F", "-I", "*.o"]).unwrap(), false);
+        assert_eq!(
+            trans.cmd_warnings,
+            ["-F has no cmd equivalent; ignored", "-I has no cmd equivalent; ignored"]
+        );
+        assert_eq!(trans.powershell_warnings, ["-F has no PowerShell equivalent; ignored"]);
+
+        // Pattern warnings follow the dropped flags
+        let trans = translate(&LsArgs::parse(["ls", "-s", "--shell-glob", "[!a]*"]).unwrap(), false);
+        assert_eq!(trans.cmd_warnings.len(), 2);
+        assert_eq!(trans.cmd_warnings[0], "-s has no cmd equivalent; ignored");
+
+        let trans = translate(&LsArgs::parse(["ls", "-1t"]).unwrap(), false);
+        assert!(trans.cmd_warnings.is_empty() && trans.powershell_warnings.is_empty());
+    }
+
+    #[test]
+    fn test_ignore_patterns() {
+        let args = LsArgs::parse(["ls", "-I", "*.o", "--hide=*~"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("-Exclude '*.o','*~'"));
+
+        let args = LsArgs::parse(["ls", "-a", "-I", "*.o", "--hide=*~"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("-Exclude '*.o'"));
+        assert!(!trans.powershell_command.contains("'*~'"));
+    }
+
+    #[test]
+    fn test_gitignore_post_filter() {
+        let args = LsArgs::parse(["ls", "--gitignore", "-R"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.contains("/B"));
+        assert!(trans.cmd_warnings.is_empty());
+        assert!(trans.powershell_command.starts_with("Get-ChildItem -Recurse"));
+        assert!(trans.powershell_command.ends_with(" | ForEach-Object { $_.FullName }"));
+        assert!(trans.powershell_command.contains("-Exclude '.git'"));
+        assert!(!trans.powershell_command.contains("check-ignore"));
+        assert!(trans.powershell_warnings.is_empty());
+
+        // Records are built from the paths too
+        let trans = translate(&LsArgs::parse(["ls", "--json", "--gitignore"]).unwrap(), false);
+        assert!(trans.powershell_command.ends_with(" | ForEach-Object { $_.FullName }"));
+
+        // Neither shell's long form can be filtered
+        let trans = translate(&LsArgs::parse(["ls", "-l", "--gitignore"]).unwrap(), false);
+        assert!(!trans.cmd_command.contains("/B"));
+        assert_eq!(trans.cmd_warnings, ["--gitignore has no cmd equivalent; ignored"]);
+        assert!(trans.powershell_command.contains("Format-Table"));
+        assert_eq!(trans.powershell_warnings, ["--gitignore has no PowerShell equivalent; ignored"]);
+    }
+
+    #[test]
+    fn test_shell_glob_translation() {
+        let trans = translate(&LsArgs::parse(["ls", "--shell-glob", "src/*.{rs,toml}"]).unwrap(), false);
+        assert!(trans.cmd_command.ends_with("dir src\\*.rs src\\*.toml"));
+        assert!(trans.powershell_command.contains("-Path src\\*.rs,src\\*.toml"));
+        assert!(trans.cmd_warnings.is_empty() && trans.powershell_warnings.is_empty());
+
+        let trans = translate(&LsArgs::parse(["ls", "--shell-glob", "src/**/*.rs"]).unwrap(), false);
+        assert!(trans.cmd_command.contains("/S") && trans.cmd_command.ends_with("src\\*.rs"));
+        assert!(trans.powershell_command.contains("-Recurse -Path src -Filter *.rs"));
+        assert!(trans.powershell_warnings.is_empty());
+
+        let trans = translate(&LsArgs::parse(["ls", "--shell-glob", "**/*.{rs,toml}"]).unwrap(), false);
+        assert!(trans.powershell_command.contains("-Path . -Include '*.rs','*.toml'"));
+
+        // Classes: dir approximates, PowerShell checks the exact pattern
+        let trans = translate(&LsArgs::parse(["ls", "--shell-glob", "[!a]*.rs"]).unwrap(), false);
+        assert!(trans.cmd_command.ends_with("dir ?*.rs"));
+        assert_eq!(trans.cmd_warnings.len(), 1);
+        assert!(trans.powershell_command.contains("-Path ?*.rs"));
+        assert!(trans.powershell_command.contains("$p -match '(^|/)[^a][^/]*\\.rs$'"));
+        assert!(trans.powershell_warnings.is_empty());
+
+        let trans = translate(&LsArgs::parse(["ls", "--shell-glob", "a/**/b/*.rs"]).unwrap(), false);
+        assert!(trans.cmd_warnings[0].contains("any depth"));
+        assert!(trans.powershell_command.contains("'(^|/)b/[^/]*\\.rs$'"));
+
+        // Without --shell-glob operands are passed through untouched
+        let trans = translate(&LsArgs::parse(["ls", "x", "y z"]).unwrap(), false);
+        assert!(trans.powershell_command.contains("-Path x,\"y z\""));
+    }
+
+    #[test]
+    fn test_zero_and_columns() {
+        let args = LsArgs::parse(["ls", "--zero"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.contains("/B"));
+        assert!(trans.powershell_command.contains("[char]0"));
+
+        let args = LsArgs::parse(["ls", "-C"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.contains("/W"));
+        assert!(trans.powershell_command.contains("Format-Wide"));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let args = LsArgs::parse(["ls", "-l", "--json"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.contains("/B"));
+        assert!(trans.powershell_command.starts_with("ConvertTo-Json -Compress -InputObject @(Get-ChildItem"));
+        assert!(!trans.powershell_command.contains("Format-Table"));
+        // A second hard link doesn't make a file a symlink
+        assert!(trans.powershell_command.contains("$l = $_.LinkType -in 'SymbolicLink','Junction'"));
+        assert!(!trans.powershell_command.contains("if ($_.LinkType)"));
+
+        let args = LsArgs::parse(["ls", "--ndjson"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.ends_with("ForEach-Object { $_ | ConvertTo-Json -Compress }"));
+    }
+
+    #[test]
+    fn test_csv_columns() {
+        let args = LsArgs::parse(["ls", "-R", "--format=csv", "--columns=name,ctime"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.contains("/S /B"));
+        assert!(trans.powershell_command.contains(
+            "Select-Object @{N='name';E={$_.Name}}, @{N='ctime';E={$_.CreationTimeUtc"
+        ));
+        assert!(!trans.powershell_command.contains("ConvertTo-Csv"));
+        assert!(trans.powershell_command.contains("-Begin { [Console]::Out.Write(\"name,ctime`r`n\") }"));
+        assert!(trans.powershell_command.contains("-join \",\") + \"`r`n\")"));
+
+        let args = LsArgs::parse(["ls", "--format=tsv", "--columns=name,size"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("Write(\"name`tsize`n\")"));
+        assert!(trans.powershell_command.contains("-replace \"`t\", '\\t'"));
+    }
+
+    #[test]
+    fn test_long_columns() {
+        let args = LsArgs::parse(["ls", "--long-columns=perms,size,ctime,name,target"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains(
+            "Format-Table Mode, Length, CreationTime, Name, @{N='Target';"
+        ));
+
+        let args = LsArgs::parse(["ls", "--long-columns=shortname,name"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.starts_with("function Get-ShortName"));
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let args = LsArgs::parse(["ls", "-1", "--hyperlink=always"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("]8;;$u"));
+        assert!(!trans.powershell_command.contains("-ExpandProperty"));
+
+        let args = LsArgs::parse(["ls", "-1", "--hyperlink=never"]).unwrap();
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("-ExpandProperty Name"));
+
+        // auto follows the terminal state passed in
+        let args = LsArgs::parse(["ls", "-1", "--hyperlink=auto"]).unwrap();
+        assert!(translate(&args, true).powershell_command.contains("]8;;$u"));
+        assert!(!translate(&args, false).powershell_command.contains("]8;;$u"));
+
+        // dir's /W columns are the one form execute can't link
+        let rating = |argv: &[&str]| {
+            let trans = translate(&LsArgs::parse(argv).unwrap(), false);
+            trans.fidelity.iter().find(|f| f.flag == "--hyperlink").unwrap().cmd
+        };
+        assert_eq!(rating(&["ls", "--hyperlink", "a", "b"]), Fidelity::Exact);
+        assert_eq!(rating(&["ls", "--hyperlink", "-l"]), Fidelity::Exact);
+        assert_eq!(rating(&["ls", "--hyperlink", "-C"]), Fidelity::Unsupported);
+    }
+
+    #[test]
+    fn test_link_targets_in_long_format() {
+        let args = LsArgs {
+            long_format: true,
+            ..Default::default()
+        };
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("LinkTarget"));
+
+        let args = LsArgs {
+            long_format: true,
+            recursive: true,
+            dereference: true,
+            ..Default::default()
+        };
+        let trans = translate(&args, false);
+        assert!(trans.powershell_command.contains("-FollowSymlink"));
+        assert!(!trans.powershell_command.contains("LinkTarget"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follows_operand_links_with_h() {
+        let dir = TempDir::new("translate-h");
+        fs::create_dir(dir.join("real")).unwrap();
+        std::os::unix::fs::symlink("real", dir.join("link")).unwrap();
+
+        let mut args = LsArgs {
+            paths: vec![dir.join("link")],
+            ..Default::default()
+        };
+        assert!(translate(&args, false).cmd_command.ends_with("link"));
+        args.dereference_command_line = true;
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.ends_with("real"));
+        assert!(trans.powershell_command.ends_with("real"));
+    }
+
+    #[test]
+    fn test_path_forms() {
+        let args = LsArgs {
+            paths: ["//srv/share", "src/x", "C:foo"].map(PathBuf::from).to_vec(),
+            ..Default::default()
+        };
+        let trans = translate(&args, false);
+        assert!(trans.cmd_command.ends_with(r"dir \\srv\share src\x C:foo"));
+        assert_eq!(
+            trans.path_forms,
+            [r"//srv/share -> \\srv\share (UNC share)", r"C:foo -> C:foo (relative to the current directory of its drive)"]
+        );
+    }
 }
@@ -0,0 +1,534 @@
+//! Built-in tree view (--tree)
+//!
+//! Walks each operand depth-first with std::fs and draws the hierarchy the
+//! way tree(1) does, so the output is the same on every platform and isn't
+//! at the mercy of the console code page.
+//!
+//! With --du each directory also carries the total size and number of files
+//! below it, and --json writes the same tree as nested objects.
+
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::args::{Charset, LsArgs, OutputFormat};
+use crate::gitignore::Gitignore;
+use crate::hyperlink;
+use crate::json;
+use crate::native::{self, Entry, EntryKind};
+use crate::quote::Quoting;
+use crate::paths::operand_path;
+
+/// Connectors: branch, last branch, continuation, blank
+const UNICODE: [&str; 4] = ["├── ", "└── ", "│   ", "    "];
+const ASCII: [&str; 4] = ["|-- ", "`-- ", "|   ", "    "];
+
+/// An entry and, for directories, what was found below it
+#[derive(Debug)]
+pub struct Node {
+    pub entry: Entry,
+    pub children: Vec<Node>,
+    pub size: u64,  // bytes; for directories, of all files below
+    pub files: u64, // files at or below this node
+}
+
+/// Draw a tree for each of `args.paths`. Returns the exit status.
+pub fn run(args: &LsArgs) -> io::Result<i32> {
+    let stdout = io::stdout();
+    let tty = stdout.is_terminal();
+    let mut out = stdout.lock();
+    let mut tree = Tree::new(args, tty);
+    tree.write(&mut out)?;
+    out.flush()?;
+    Ok(tree.walker.status)
+}
+
+/// Builds Nodes, applying the depth limit and name filters
+pub struct Walker<'a> {
+    args: &'a LsArgs,
+    pub status: i32,
+    quiet: bool, // walking what the filters leave off the tree, for --du
+}
+
+impl<'a> Walker<'a> {
+    pub fn new(args: &'a LsArgs) -> Self {
+        Walker { args, status: 0, quiet: false }
+    }
+
+    /// Report a problem, unless it's below an entry the tree doesn't show
+    fn error(&mut self, message: String, status: i32) {
+        if self.quiet {
+            return;
+        }
+        eprintln!("ls: {}", message);
+        self.status = self.status.max(status);
+    }
+
+    /// Stat an operand and walk it, or report why it can't be
+    pub fn walk_operand(&mut self, operand: &Path) -> Option<Node> {
+        let name = operand.to_string_lossy();
+        match Entry::from_path(&operand_path(operand), &name, true) {
+            Ok(entry) => Some(self.walk(entry, 0, &mut Vec::new(), None)),
+            Err(e) => {
+                let message = format!("cannot access '{}': {}", name, native::describe_error(&e));
+                self.error(message, 2);
+                None
+            }
+        }
+    }
+
+    /// Walk below `entry`. `parent_rules` are the --gitignore rules of the
+    /// directory holding it.
+    fn walk(
+        &mut self,
+        entry: Entry,
+        depth: usize,
+        ancestors: &mut Vec<PathBuf>,
+        parent_rules: Option<&Gitignore>,
+    ) -> Node {
+        let args = self.args;
+        if entry.kind != EntryKind::Dir {
+            return Node { size: entry.size, files: 1, entry, children: Vec::new() };
+        }
+        let mut node = Node { entry, children: Vec::new(), size: 0, files: 0 };
+
+        // --du still has to see below the depth limit to add it up
+        let beyond_limit = args.tree_depth.is_some_and(|max| depth >= max);
+        if beyond_limit && !args.du {
+            return node;
+        }
+
+        // With -L a link can lead back to a directory we're already inside
+        let path = node.entry.path.clone();
+        let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if ancestors.contains(&key) {
+            self.error(format!("{}: not listing already-listed directory", path.display()), 2);
+            return node;
+        }
+
+        let read = match fs::read_dir(&path) {
+            Ok(read) => read,
+            Err(e) => {
+                let message = format!(
+                    "cannot open directory '{}': {}",
+                    path.display(),
+                    native::describe_error(&e)
+                );
+                self.error(message, if depth == 0 { 2 } else { 1 });
+                return node;
+            }
+        };
+
+        let rules = args.gitignore.then(|| match parent_rules {
+            Some(parent) => parent.child(&path),
+            None => Gitignore::for_dir(&path),
+        });
+        // --du adds up what the filters keep off the tree too, as du would
+        let mut entries = Vec::new();
+        let mut unseen = Vec::new();
+        for item in read {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    let message =
+                        format!("reading directory '{}': {}", path.display(), native::describe_error(&e));
+                    self.error(message, 1);
+                    continue;
+                }
+            };
+            let name = item.file_name().to_string_lossy().into_owned();
+            let filtered = native::is_ignored(args, &name) || native::is_git_ignored(rules.as_ref(), &item);
+            if filtered && !args.du {
+                continue;
+            }
+            match Entry::from_path(&item.path(), &name, args.dereference) {
+                Ok(entry) if !filtered && self.keep(&entry) => entries.push(entry),
+                Ok(entry) if args.du => unseen.push(entry),
+                Ok(_) | Err(_) if filtered => {}
+                Ok(_) => {}
+                Err(e) => {
+                    let message = format!(
+                        "cannot access '{}': {}",
+                        path.join(&name).display(),
+                        native::describe_error(&e)
+                    );
+                    self.error(message, 1);
+                }
+            }
+        }
+        native::sort_entries(&mut entries, args);
+
+        ancestors.push(key);
+        node.children = entries
+            .into_iter()
+            .map(|entry| self.walk(entry, depth + 1, ancestors, rules.as_ref()))
+            .collect();
+        // What the tree doesn't show can't fail it: nothing below is
+        // reported, and the exit status stays as it was
+        let quiet = std::mem::replace(&mut self.quiet, true);
+        let unseen: Vec<Node> = unseen
+            .into_iter()
+            .map(|entry| self.walk(entry, depth + 1, ancestors, rules.as_ref()))
+            .collect();
+        self.quiet = quiet;
+        ancestors.pop();
+
+        node.size = node.children.iter().chain(&unseen).map(|c| c.size).sum();
+        node.files = node.children.iter().chain(&unseen).map(|c| c.files).sum();
+
+        // Files still count toward the totals when -d or the depth limit
+        // keeps them off the tree
+        if args.directory {
+            node.children.retain(|c| is_dir(&c.entry));
+        }
+        if beyond_limit {
+            node.children.clear();
+        }
+        if args.du && args.sort_by_size && !args.no_sort {
+            node.children.sort_by_key(|c| std::cmp::Reverse(c.size));
+            if args.reverse {
+                node.children.reverse();
+            }
+        }
+        node
+    }
+
+    /// Hidden files need -a/-A; --include patterns select files but never
+    /// prune directories
+    fn keep(&self, entry: &Entry) -> bool {
+        let args = self.args;
+        if entry.hidden && !(args.all || args.almost_all) {
+            return false;
+        }
+        is_dir(entry)
+            || args.include.is_empty()
+            || args.include.iter().any(|p| crate::glob::matches(p, &entry.name))
+    }
+}
+
+/// A directory, or a link to one
+fn is_dir(entry: &Entry) -> bool {
+    entry.kind == EntryKind::Dir || entry.target_kind == Some(EntryKind::Dir)
+}
+
+fn plural(n: u64, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
+
+struct Tree<'a> {
+    args: &'a LsArgs,
+    walker: Walker<'a>,
+    quoting: Quoting,
+    glyphs: &'static [&'static str; 4],
+    link_host: Option<String>, // set when names are hyperlinked
+    now: SystemTime,
+    dirs: u64,
+    files: u64,
+    total: u64,
+}
+
+impl<'a> Tree<'a> {
+    fn new(args: &'a LsArgs, tty: bool) -> Self {
+        Tree {
+            args,
+            walker: Walker::new(args),
+            quoting: Quoting::resolve(args, tty),
+            glyphs: match args.charset {
+                Charset::Unicode => &UNICODE,
+                Charset::Ascii => &ASCII,
+            },
+            link_host: hyperlink::enabled(args, tty).then(hyperlink::hostname),
+            now: SystemTime::now(),
+            dirs: 0,
+            files: 0,
+            total: 0,
+        }
+    }
+
+    fn write(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let args = self.args;
+        if args.output == OutputFormat::Json {
+            write!(out, "[")?;
+        }
+
+        let mut count = 0;
+        for operand in &args.paths {
+            // Keep earlier trees ahead of this one's error messages
+            out.flush()?;
+            let Some(root) = self.walker.walk_operand(operand) else {
+                continue;
+            };
+            match args.output {
+                OutputFormat::Json => {
+                    let separator = if count == 0 { "\n" } else { ",\n" };
+                    write!(out, "{}{}", separator, self.node_object(&root))?;
+                }
+                OutputFormat::Ndjson => writeln!(out, "{}", self.node_object(&root))?,
+                _ => {
+                    let mut line = self.link(&self.quoting.quote(&root.entry.name), &root.entry.path);
+                    if args.du {
+                        line = format!("[{}]  {}", self.meta(&root).join(" "), line);
+                    }
+                    writeln!(out, "{}", line)?;
+                    self.total += root.size;
+                    self.write_children(out, &root, "")?;
+                }
+            }
+            count += 1;
+        }
+
+        match args.output {
+            OutputFormat::Json => writeln!(out, "{}]", if count == 0 { "" } else { "\n" }),
+            OutputFormat::Ndjson => Ok(()),
+            _ => {
+                let mut summary = plural(self.dirs, "directory", "directories");
+                if !args.directory {
+                    summary.push_str(&format!(", {}", plural(self.files, "file", "files")));
+                }
+                if args.du {
+                    summary = format!("{} used in {}", self.format_size(self.total).trim_start(), summary);
+                }
+                writeln!(out, "\n{}", summary)
+            }
+        }
+    }
+
+    /// The entry's JSON object with the tree keys added: `children` for
+    /// directories and, with --du, `total_size` and `file_count`
+    fn node_object(&self, node: &Node) -> String {
+        let mut object = json::entry_object(&node.entry);
+        if node.entry.kind != EntryKind::Dir {
+            return object;
+        }
+
+        object.pop(); // reopen the object
+        if self.args.du {
+            object.push_str(&format!(",\"total_size\":{},\"file_count\":{}", node.size, node.files));
+        }
+        let children: Vec<String> = node.children.iter().map(|c| self.node_object(c)).collect();
+        object.push_str(&format!(",\"children\":[{}]}}", children.join(",")));
+        object
+    }
+
+    fn write_children(&mut self, out: &mut dyn Write, node: &Node, prefix: &str) -> io::Result<()> {
+        for (i, child) in node.children.iter().enumerate() {
+            let last = i + 1 == node.children.len();
+            let (branch, indent) = if last {
+                (self.glyphs[1], self.glyphs[3])
+            } else {
+                (self.glyphs[0], self.glyphs[2])
+            };
+            writeln!(out, "{}{}{}", prefix, branch, self.label(child))?;
+
+            if child.entry.kind == EntryKind::Dir {
+                self.dirs += 1;
+            } else {
+                self.files += 1;
+            }
+            self.write_children(out, child, &format!("{}{}", prefix, indent))?;
+        }
+        Ok(())
+    }
+
+    /// `[perms size date]  name -> target`, with the bracket from -l/-s/--du
+    fn label(&self, node: &Node) -> String {
+        let args = self.args;
+        let entry = &node.entry;
+        let mut label = String::new();
+
+        let meta = self.meta(node);
+        if !meta.is_empty() {
+            label.push_str(&format!("[{}]  ", meta.join(" ")));
+        }
+
+        label.push_str(&self.link(&self.quoting.quote(&entry.name), &entry.path));
+        match &entry.link_target {
+            Some(target) => {
+                label.push_str(" -> ");
+                label.push_str(&self.quoting.quote(&target.to_string_lossy()));
+                if let (true, Some(kind)) = (args.classify, entry.target_kind) {
+                    label.push_str(native::indicator(kind, None));
+                }
+            }
+            None if args.classify => label.push_str(native::indicator(entry.kind, entry.mode)),
+            None => {}
+        }
+        label
+    }
+
+    /// The bracketed fields before a name. With --du a directory's size is
+    /// its total, followed by its file count.
+    fn meta(&self, node: &Node) -> Vec<String> {
+        let args = self.args;
+        let mut meta = Vec::new();
+        if args.long_format {
+            meta.push(native::permissions_string(&node.entry));
+        }
+        if args.du {
+            let mut size = self.format_size(node.size);
+            if node.entry.kind == EntryKind::Dir {
+                size.push_str(&format!(", {}", plural(node.files, "file", "files")));
+            }
+            meta.push(size);
+        } else if args.long_format || args.show_size {
+            meta.push(self.format_size(node.entry.size));
+        }
+        if args.long_format {
+            meta.push(native::format_time(node.entry.modified, self.now));
+        }
+        meta
+    }
+
+    fn format_size(&self, size: u64) -> String {
+        if self.args.human_readable {
+            format!("{:>4}", native::human_size(size))
+        } else {
+            format!("{:>11}", size)
+        }
+    }
+
+    fn link(&self, name: &str, path: &Path) -> String {
+        match &self.link_host {
+            Some(host) => hyperlink::wrap(name, path, host),
+            None => name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TempDir;
+
+    fn temp_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("tree-{}", name));
+        fs::create_dir_all(dir.join("src/bin")).unwrap();
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "").unwrap();
+        fs::write(dir.join(".env"), "").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join("src/lib.o"), "").unwrap();
+        fs::write(dir.join("src/bin/tool.rs"), "").unwrap();
+        dir
+    }
+
+    fn tree_to_string(args: &LsArgs) -> (String, i32) {
+        let mut out = Vec::new();
+        let mut tree = Tree::new(args, false);
+        tree.write(&mut out).unwrap();
+        (String::from_utf8(out).unwrap(), tree.walker.status)
+    }
+
+    fn args_for(dir: &Path, flags: &[&str]) -> LsArgs {
+        let mut argv = vec!["ls", "--tree"];
+        argv.extend(flags);
+        let dir = dir.to_string_lossy();
+        argv.push(&dir);
+        LsArgs::parse(argv).unwrap()
+    }
+
+    #[test]
+    fn test_unicode_tree() {
+        let dir = temp_dir("unicode");
+        let (out, status) = tree_to_string(&args_for(&dir, &[]));
+        let expected = format!(
+            "{}\n\
+             ├── Cargo.toml\n\
+             ├── docs\n\
+             └── src\n    \
+                 ├── bin\n    \
+                 │   └── tool.rs\n    \
+                 ├── lib.o\n    \
+                 └── main.rs\n\
+             \n3 directories, 4 files\n",
+            dir.display()
+        );
+        assert_eq!(out, expected);
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn test_ascii_depth_and_hidden() {
+        let dir = temp_dir("ascii");
+        let (out, _) = tree_to_string(&args_for(&dir, &["--charset=ascii", "-a", "-L", "1"]));
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            ["|-- .env", "|-- Cargo.toml", "|-- docs", "`-- src", "", "2 directories, 2 files"]
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let dir = temp_dir("filters");
+        let (out, _) = tree_to_string(&args_for(&dir, &["-d"]));
+        assert!(out.contains("bin") && !out.contains("main.rs"));
+        assert!(out.ends_with("\n3 directories\n"));
+
+        let (out, _) = tree_to_string(&args_for(&dir, &["--include=*.rs", "-I", "bin"]));
+        assert!(out.contains("main.rs") && out.contains("docs"));
+        assert!(!out.contains("Cargo.toml") && !out.contains("lib.o") && !out.contains("tool.rs"));
+    }
+
+    #[test]
+    fn test_du_totals() {
+        let dir = temp_dir("du");
+        fs::write(dir.join("src/main.rs"), "x".repeat(300)).unwrap();
+        fs::write(dir.join("src/bin/tool.rs"), "x".repeat(20)).unwrap();
+        fs::write(dir.join("docs/guide.md"), "x".repeat(500)).unwrap();
+
+        // Sorted by total size; files below the depth limit, and hidden
+        // ones, still count
+        let (out, _) = tree_to_string(&args_for(&dir, &["--du", "-S", "-d", "-L", "1"]));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], format!("[        820, 6 files]  {}", dir.display()));
+        assert_eq!(lines[1], "├── [        500, 1 file]  docs");
+        assert_eq!(lines[2], "└── [        320, 3 files]  src");
+        assert_eq!(lines[4], "820 used in 2 directories");
+
+        let (out, _) = tree_to_string(&args_for(&dir, &["--du", "--include=*.md", "-I", "bin"]));
+        assert!(out.starts_with(&format!("[        820, 6 files]  {}", dir.display())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_du_ignored_dir_errors() {
+        // A link loop inside an ignored directory would be an error if
+        // the tree showed it; it only adds to the totals here
+        let dir = temp_dir("du-ignored");
+        fs::create_dir(dir.join("target")).unwrap();
+        fs::write(dir.join("target/app"), "x".repeat(100)).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("target/loop")).unwrap();
+
+        let (out, status) = tree_to_string(&args_for(&dir, &["--du", "--dereference", "-I", "target"]));
+        assert!(out.starts_with(&format!("[        100, 6 files]  {}", dir.display())));
+        assert!(!out.contains("target"));
+        assert_eq!(status, 0);
+
+        // Shown, the loop is reported
+        let (_, status) = tree_to_string(&args_for(&dir, &["--du", "--dereference"]));
+        assert_eq!(status, 2);
+    }
+
+    #[test]
+    fn test_json_nesting() {
+        let dir = temp_dir("json");
+        let (out, _) = tree_to_string(&args_for(&dir, &["--json", "--du", "-I", "src"]));
+        assert!(out.starts_with("[\n{\"version\":1,"));
+        assert!(out.ends_with("}\n]\n"));
+        // src is left off the tree but still counts toward the totals
+        assert!(out.contains("\"total_size\":0,\"file_count\":5,\"children\":[{\"version\":1,\"name\":\"Cargo.toml\""));
+        assert!(out.contains("\"name\":\"docs\""));
+        assert!(out.contains("\"file_count\":0,\"children\":[]}"));
+        assert!(!out.contains("main.rs"));
+    }
+
+    #[test]
+    fn test_missing_operand() {
+        let args = args_for(Path::new("/definitely/not/here"), &[]);
+        let (out, status) = tree_to_string(&args);
+        assert_eq!(out, "\n0 directories, 0 files\n");
+        assert_eq!(status, 2);
+    }
+}
@@ -0,0 +1,592 @@
+//! A PowerShell that stays running between calls
+//!
+//! Starting powershell.exe costs 300-800 ms, far more than the listing
+//! itself. With --worker (or LS_WRAPPER_WORKER=1) ls hands its script to a
+//! host process instead. The host keeps one PowerShell running and exits
+//! once nobody has used it for LS_WRAPPER_WORKER_IDLE seconds (600 by
+//! default). Anything else that wants a warm PowerShell can go through
+//! `Client` the same way.
+//!
+//! The host listens on a loopback port. It writes the port and a random
+//! token to a file in the user's local app data, and a client has to send
+//! the token before anything else.
+//!
+//! Every frame is one line, in both directions:
+//!
+//! ```text
+//! host -> PowerShell    RUN <directory> <script>, both base64 UTF-8
+//! PowerShell -> host    READY 1                once, on starting
+//!                       O <a line of output>
+//!                       R <what the script wrote to the console itself,
+//!                          as base64 UTF-8>
+//!                       E <an error, as "Command : message">
+//!                       DONE <0, or 1 if there were errors>
+//! ```
+//!
+//! DONE always follows an empty line, so stray output without a newline
+//! (a native program writing to the worker's stdout) can't run into it and
+//! leave the host waiting for an answer that never comes.
+//!
+//! ```text
+//! client -> host        <token>, <its current directory>, then the script
+//!                       up to end of stream
+//! host -> client        O, R, E and DONE as above, or FAIL <why>
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::execute::{base64, unbase64};
+use crate::shells;
+
+/// The protocol version a worker announces in its READY line
+const PROTOCOL: &str = "1";
+
+/// How long to wait for a host that's just been started
+const HOST_START: Duration = Duration::from_secs(10);
+
+/// How often an idle host checks for a client
+const POLL: Duration = Duration::from_millis(50);
+
+/// Runs each RUN line's script from the client's directory and answers in
+/// frames. Errors are collected from $Error rather than PowerShell's
+/// multi-line rendering of them. Whatever the script writes to the console
+/// itself, such as --zero's NUL-terminated names, is caught in a
+/// StringWriter and sent on as one R frame.
+const POWERSHELL_LOOP: &str = "\
+$ErrorActionPreference = 'Continue'; \
+[Console]::OutputEncoding = New-Object Text.UTF8Encoding $false; \
+$frames = [Console]::Out; \
+$frames.WriteLine('READY 1'); $frames.Flush(); \
+$decode = { [Text.Encoding]::UTF8.GetString([Convert]::FromBase64String($args[0])) }; \
+while ($null -ne ($request = [Console]::In.ReadLine())) { \
+    $fields = $request.Split(' '); \
+    if ($fields.Count -ne 3 -or $fields[0] -ne 'RUN') { continue }; \
+    $Error.Clear(); \
+    $raw = New-Object IO.StringWriter; [Console]::SetOut($raw); \
+    try { \
+        Set-Location -LiteralPath (& $decode $fields[1]) -ErrorAction Stop; \
+        & ([ScriptBlock]::Create((& $decode $fields[2]))) 2>$null | Out-String -Stream -Width 4096 | \
+            ForEach-Object { $frames.WriteLine('O ' + $_) } \
+    } catch { $frames.WriteLine('E ' + ($_.Exception.Message -replace '\\r?\\n', ' ')) } \
+    finally { [Console]::SetOut($frames) }; \
+    $text = $raw.ToString(); \
+    if ($text) { $frames.WriteLine('R ' + [Convert]::ToBase64String([Text.Encoding]::UTF8.GetBytes($text))) }; \
+    $records = @($Error); [Array]::Reverse($records); \
+    foreach ($record in $records) { \
+        $name = $record.InvocationInfo.MyCommand.Name; \
+        $frames.WriteLine('E ' + $name + ' : ' + ($record.Exception.Message -replace '\\r?\\n', ' ')) \
+    }; \
+    $frames.WriteLine(); \
+    $frames.WriteLine('DONE ' + [int]($records.Count -gt 0)); \
+    $frames.Flush() \
+}";
+
+/// One line of the protocol, as a worker or host sends it back
+#[derive(Debug, PartialEq)]
+pub enum Frame<'a> {
+    Out(&'a str),
+    Raw(&'a str), // still base64; see `Frame::raw_text`
+    Err(&'a str),
+    Done(i32),
+}
+
+impl<'a> Frame<'a> {
+    /// Parse one line, ending included
+    pub fn parse(line: &'a str) -> Option<Frame<'a>> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if let Some(text) = line.strip_prefix("O ") {
+            Some(Frame::Out(text))
+        } else if let Some(text) = line.strip_prefix("R ") {
+            Some(Frame::Raw(text))
+        } else if let Some(text) = line.strip_prefix("E ") {
+            Some(Frame::Err(text))
+        } else {
+            line.strip_prefix("DONE ")?.parse().ok().map(Frame::Done)
+        }
+    }
+}
+
+impl Frame<'_> {
+    /// What an R frame carries, decoded
+    pub fn raw_text(&self) -> io::Result<String> {
+        let Frame::Raw(data) = self else {
+            return Ok(String::new());
+        };
+        let bytes = unbase64(data).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad R frame"))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl fmt::Display for Frame<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frame::Out(text) => write!(f, "O {}", text),
+            Frame::Raw(text) => write!(f, "R {}", text),
+            Frame::Err(text) => write!(f, "E {}", text),
+            Frame::Done(status) => write!(f, "DONE {}", status),
+        }
+    }
+}
+
+/// A worker process, started when first needed and again after it dies
+pub struct Worker {
+    program: OsString,
+    args: Vec<OsString>,
+    process: Option<Process>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    pub fn new<S: Into<OsString>>(program: S, args: Vec<OsString>) -> Worker {
+        Worker {
+            program: program.into(),
+            args,
+            process: None,
+        }
+    }
+
+    pub fn powershell() -> Worker {
+        let args = ["-NoProfile", "-NoLogo", "-NonInteractive", "-Command", POWERSHELL_LOOP];
+        Worker::new(shells::powershell().program, args.iter().map(OsString::from).collect())
+    }
+
+    /// Run a script from the directory `cwd`, handing each O, R and E frame to
+    /// `each`, and return its DONE status. If the worker has died since the last call, or dies
+    /// before writing anything back, the script goes to a fresh one. An
+    /// error from `each` is returned once the worker has finished, so the
+    /// next script starts in step.
+    pub fn run(&mut self, cwd: &str, script: &str, each: &mut dyn FnMut(&Frame) -> io::Result<()>) -> io::Result<i32> {
+        let mut answered = false;
+        for attempt in 0..2 {
+            let process = match &mut self.process {
+                Some(process) => process,
+                None => self.process.insert(self.start()?),
+            };
+            let mut handed_on = Ok(());
+            let result = process.run(cwd, script, &mut |frame| {
+                answered = true;
+                if handed_on.is_ok() {
+                    handed_on = each(frame);
+                }
+            });
+            match result {
+                Ok(status) => return handed_on.map(|_| status),
+                Err(e) => {
+                    self.stop();
+                    if answered || attempt == 1 {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        unreachable!("the second attempt returns")
+    }
+
+    fn start(&self) -> io::Result<Process> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut process = Process { child, stdin, stdout };
+
+        let ready = process.read_line()?;
+        if ready.trim_start_matches('\u{feff}').trim_end() != format!("READY {}", PROTOCOL) {
+            process.child.kill().ok();
+            process.child.wait().ok();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("worker didn't start: {:?}", ready.trim_end()),
+            ));
+        }
+        Ok(process)
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            process.child.kill().ok();
+            process.child.wait().ok();
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Process {
+    fn run(&mut self, cwd: &str, script: &str, each: &mut dyn FnMut(&Frame)) -> io::Result<i32> {
+        writeln!(self.stdin, "RUN {} {}", base64(cwd.as_bytes()), base64(script.as_bytes()))?;
+        self.stdin.flush()?;
+        loop {
+            let line = self.read_line()?;
+            match Frame::parse(&line) {
+                Some(Frame::Done(status)) => return Ok(status),
+                Some(frame) => each(&frame),
+                None => {}
+            }
+        }
+    }
+
+    /// The next line, or an error once the worker has gone
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        if self.stdout.read_until(b'\n', &mut bytes)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "worker exited"));
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// A connection to the host
+pub struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    /// Connect to the running host, starting one if there's none
+    pub fn connect() -> io::Result<Client> {
+        if let Ok(client) = Client::connect_running() {
+            return Ok(client);
+        }
+        Command::new(env::current_exe()?)
+            .arg("--worker-host")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .detached()
+            .spawn()?;
+
+        let started = Instant::now();
+        loop {
+            match Client::connect_running() {
+                Ok(client) => return Ok(client),
+                Err(e) if started.elapsed() > HOST_START => return Err(e),
+                Err(_) => thread::sleep(POLL),
+            }
+        }
+    }
+
+    fn connect_running() -> io::Result<Client> {
+        let state = fs::read_to_string(state_file())?;
+        let mut fields = state.split_whitespace();
+        let (Some(port), Some(token)) = (fields.next(), fields.next()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad worker state file"));
+        };
+        let port = port
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad worker port"))?;
+        Client::connect_to(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), token)
+    }
+
+    pub fn connect_to(addr: SocketAddr, token: &str) -> io::Result<Client> {
+        let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(1))?;
+        writeln!(stream, "{}", token)?;
+        Ok(Client { stream })
+    }
+
+    /// Run a script on the host's worker, from the directory `cwd`; see
+    /// `Worker::run`
+    pub fn run(mut self, cwd: &str, script: &str, each: &mut dyn FnMut(&Frame) -> io::Result<()>) -> io::Result<i32> {
+        writeln!(self.stream, "{}", cwd)?;
+        self.stream.write_all(script.as_bytes())?;
+        self.stream.shutdown(Shutdown::Write)?;
+
+        let mut reader = BufReader::new(self.stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "worker host hung up"));
+            }
+            if let Some(why) = line.strip_prefix("FAIL ") {
+                return Err(io::Error::other(why.trim_end().to_string()));
+            }
+            match Frame::parse(&line) {
+                Some(Frame::Done(status)) => return Ok(status),
+                Some(frame) => each(&frame)?,
+                None => {}
+            }
+        }
+    }
+}
+
+/// Run as the host (ls --worker-host) until idle
+pub fn host() -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let port = listener.local_addr()?.port();
+    let token = token();
+    let state = format!("{} {}\n", port, token);
+    write_state(&state)?;
+
+    let result = serve(listener, &token, Worker::powershell(), idle_timeout());
+
+    // Leave the file alone if a newer host has taken over
+    if fs::read_to_string(state_file()).is_ok_and(|current| current == state) {
+        fs::remove_file(state_file()).ok();
+    }
+    result
+}
+
+/// Answer clients one at a time until none has come for `idle`
+pub fn serve(listener: TcpListener, token: &str, mut worker: Worker, idle: Duration) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let mut last_used = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // A client that goes away mid-answer is no reason to stop
+                serve_client(stream, token, &mut worker).ok();
+                last_used = Instant::now();
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if last_used.elapsed() >= idle {
+                    return Ok(());
+                }
+                thread::sleep(POLL);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn serve_client(stream: TcpStream, token: &str, worker: &mut Worker) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != token {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong worker token"));
+    }
+    let mut cwd = String::new();
+    reader.read_line(&mut cwd)?;
+    let mut script = String::new();
+    reader.read_to_string(&mut script)?;
+
+    let mut writer = io::BufWriter::new(stream);
+    let result = worker.run(cwd.trim_end_matches(['\r', '\n']), &script, &mut |frame| writeln!(writer, "{}", frame));
+    match result {
+        Ok(status) => writeln!(writer, "{}", Frame::Done(status))?,
+        Err(e) => writeln!(writer, "FAIL {}", e)?,
+    }
+    writer.flush()
+}
+
+/// Where the host leaves its port and token
+fn state_file() -> PathBuf {
+    env::var_os("LOCALAPPDATA")
+        .or_else(|| env::var_os("XDG_RUNTIME_DIR"))
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("ls-wrapper-worker")
+}
+
+/// Write the state file whole, readable by the user alone
+fn write_state(state: &str) -> io::Result<()> {
+    let path = state_file();
+    let partial = path.with_extension(format!("{}", std::process::id()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&partial)?.write_all(state.as_bytes())?;
+    fs::rename(&partial, &path)
+}
+
+/// 128 random bits, from the OS-seeded keys std gives each RandomState
+fn token() -> String {
+    let half = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(Instant::now().elapsed().as_nanos());
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(), half())
+}
+
+fn idle_timeout() -> Duration {
+    let seconds = env::var("LS_WRAPPER_WORKER_IDLE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(600);
+    Duration::from_secs(seconds)
+}
+
+/// Starting the host without tying it to this console
+trait Detached {
+    fn detached(&mut self) -> &mut Self;
+}
+
+impl Detached for Command {
+    #[cfg(windows)]
+    fn detached(&mut self) -> &mut Self {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        self.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW)
+    }
+
+    #[cfg(not(windows))]
+    fn detached(&mut self) -> &mut Self {
+        self
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Stands in for PowerShell: "missing" fails, "crash" dies after one
+    /// line, "exit" answers then quits, "pwd" prints the directory it ran
+    /// from, "zero" writes NUL-terminated names, "stray" leaves output
+    /// without a newline, and anything else is echoed with the worker's pid
+    const STAND_IN: &str = r#"
+        echo READY 1
+        while read -r verb dir request; do
+            [ "$verb" = RUN ] || continue
+            cd "$(printf %s "$dir" | base64 -d)" || { echo "E Set-Location : no such directory"; echo "DONE 1"; continue; }
+            script=$(printf %s "$request" | base64 -d)
+            case "$script" in
+                pwd) echo "O $(pwd)"; echo "DONE 0" ;;
+                zero) echo "R $(printf 'a\0b c\0' | base64)"; echo; echo "DONE 0" ;;
+                stray) printf 'a\0b\0'; echo; echo "DONE 0" ;;
+                missing) echo "E Get-ChildItem : Cannot find path 'x' because it does not exist."; echo "DONE 1" ;;
+                crash) echo "O partial"; exit 3 ;;
+                exit) echo "O bye"; echo "DONE 0"; exit 0 ;;
+                *) echo "O $script"; echo "O pid $$"; echo "DONE 0" ;;
+            esac
+        done
+    "#;
+
+    fn stand_in() -> Worker {
+        Worker::new("sh", vec!["-c".into(), STAND_IN.into()])
+    }
+
+    fn collect(run: impl FnOnce(&mut dyn FnMut(&Frame) -> io::Result<()>) -> io::Result<i32>) -> (io::Result<i32>, Vec<String>) {
+        let mut lines = Vec::new();
+        let status = run(&mut |frame| {
+            lines.push(frame.to_string());
+            Ok(())
+        });
+        (status, lines)
+    }
+
+    #[test]
+    fn test_frames() {
+        assert_eq!(Frame::parse("O  a b\r\n"), Some(Frame::Out(" a b")));
+        assert_eq!(Frame::parse("E x : y\n"), Some(Frame::Err("x : y")));
+        assert_eq!(Frame::parse("DONE 1\n"), Some(Frame::Done(1)));
+        assert_eq!(Frame::parse("DONE x"), None);
+        assert_eq!(Frame::parse("READY 1"), None);
+        assert_eq!(Frame::Out("a").to_string(), "O a");
+        assert_eq!(Frame::parse("R YQA=\n"), Some(Frame::Raw("YQA=")));
+        assert_eq!(Frame::Raw("YQA=").raw_text().unwrap(), "a\0");
+        assert!(Frame::Raw("!").raw_text().is_err());
+    }
+
+    #[test]
+    fn test_worker_runs_scripts() {
+        let mut worker = stand_in();
+        let (status, first) = collect(|each| worker.run(".", "Get-ChildItem é", each));
+        assert_eq!(status.unwrap(), 0);
+        assert_eq!(first[0], "O Get-ChildItem é");
+
+        // The same process answers again
+        let (_, second) = collect(|each| worker.run(".", "again", each));
+        assert_eq!(second[1], first[1]);
+
+        let (status, lines) = collect(|each| worker.run(".", "missing", each));
+        assert_eq!(status.unwrap(), 1);
+        assert_eq!(lines, ["E Get-ChildItem : Cannot find path 'x' because it does not exist."]);
+
+        // Names written to the console itself come back whole
+        let mut raw = String::new();
+        let status = worker.run(".", "zero", &mut |frame| {
+            raw.push_str(&frame.raw_text()?);
+            Ok(())
+        });
+        assert_eq!(status.unwrap(), 0);
+        assert_eq!(raw, "a\0b c\0");
+
+        // Stray output can't swallow DONE, so the worker stays in step
+        let (status, lines) = collect(|each| worker.run(".", "stray", each));
+        assert_eq!(status.unwrap(), 0);
+        assert!(lines.is_empty());
+        let (_, lines) = collect(|each| worker.run(".", "again", each));
+        assert_eq!(lines[0], "O again");
+    }
+
+    #[test]
+    fn test_worker_recovers() {
+        let mut worker = stand_in();
+        let (_, lines) = collect(|each| worker.run(".", "exit", each));
+        assert_eq!(lines, ["O bye"]);
+
+        // The worker quit in between, so a new one takes the script
+        let (status, lines) = collect(|each| worker.run(".", "hello", each));
+        assert_eq!(status.unwrap(), 0);
+        assert_eq!(lines[0], "O hello");
+
+        // Dying halfway can't be retried without repeating output
+        let (status, lines) = collect(|each| worker.run(".", "crash", each));
+        assert!(status.is_err());
+        assert_eq!(lines, ["O partial"]);
+        let (status, _) = collect(|each| worker.run(".", "hello", each));
+        assert_eq!(status.unwrap(), 0);
+
+        let mut broken = Worker::new("sh", vec!["-c".into(), "echo hello".into()]);
+        assert!(broken.run(".", "x", &mut |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_host() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let host = thread::spawn(move || serve(listener, "secret", stand_in(), Duration::from_millis(500)));
+
+        let (status, lines) = collect(|each| Client::connect_to(addr, "secret").unwrap().run(".", "hello", each));
+        assert_eq!(status.unwrap(), 0);
+        assert_eq!(lines[0], "O hello");
+        let (_, again) = collect(|each| Client::connect_to(addr, "secret").unwrap().run(".", "hello", each));
+        assert_eq!(again[1], lines[1]);
+
+        let (status, lines) = collect(|each| Client::connect_to(addr, "guess").unwrap().run(".", "hello", each));
+        assert!(status.is_err());
+        assert!(lines.is_empty());
+
+        // Each script runs from the client's directory, not the host's
+        let root = env::temp_dir().canonicalize().unwrap();
+        for dir in [root.as_path(), Path::new("/")] {
+            let cwd = dir.to_str().unwrap();
+            let (status, lines) = collect(|each| Client::connect_to(addr, "secret").unwrap().run(cwd, "pwd", each));
+            assert_eq!(status.unwrap(), 0);
+            assert_eq!(lines, [format!("O {}", cwd)]);
+        }
+
+        // Left alone, the host stops
+        host.join().unwrap().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}